@@ -0,0 +1,196 @@
+//! Animation setup cost of spawning many units. Spawns spider rigs into an `App` and times the update that runs
+//! `init_animation_graph`, with the graph built fresh and with it already cached, next to building a graph per
+//! instance like before. Run with `cargo bench --bench anim_graph`.
+
+use std::time::{Duration, Instant};
+
+use bevy::{
+    animation::{AnimationTarget, AnimationTargetId, Interpolation, Keyframes, VariableCurve},
+    prelude::*,
+};
+use eldritch_game::{
+    animation::{
+        init_animation_graph, AdditiveLayerBones, AnimName, AnimNotifies, AnimationIndices,
+        RootMotion, SharedAnimGraph,
+    },
+    mesh_assets::{MeshAssets, StingerClip},
+    units::{enemy::EnemyUnitAnim, spider::Spider},
+};
+
+/// Bone names of the rig, the `Head` of the spider's flinch layer mask included
+const BONES: usize = 30;
+/// Clips of the glTF the code doesn't refer to, real exports carry a lot of these
+const EXTRA_CLIPS: usize = 36;
+const RUNS: u32 = 10;
+
+fn bone_names() -> Vec<Name> {
+    (0..BONES - 1)
+        .map(|i| Name::new(format!("Bone{i}")))
+        .chain([Name::new("Head")])
+        .collect()
+}
+
+fn clip(targets: &[AnimationTargetId]) -> AnimationClip {
+    let mut clip = AnimationClip::default();
+    for target in targets {
+        clip.add_curve_to_target(
+            *target,
+            VariableCurve {
+                keyframe_timestamps: vec![0.0, 0.5, 1.0],
+                keyframes: Keyframes::Rotation(vec![Quat::IDENTITY; 3]),
+                interpolation: Interpolation::Linear,
+            },
+        );
+    }
+    // Root bone walks forward so the root motion bone is found
+    clip.add_curve_to_target(
+        targets[0],
+        VariableCurve {
+            keyframe_timestamps: vec![0.0, 1.0],
+            keyframes: Keyframes::Translation(vec![Vec3::ZERO, Vec3::NEG_Z]),
+            interpolation: Interpolation::Linear,
+        },
+    );
+    clip
+}
+
+/// Stand-in for the spider glTF, only what `init_animation_graph` reads
+fn gltf(targets: &[AnimationTargetId], clip_assets: &mut Assets<AnimationClip>) -> Gltf {
+    let names = StingerClip::ALL
+        .iter()
+        .map(|clip| clip.name().to_string())
+        .chain((0..EXTRA_CLIPS).map(|i| format!("Extra{i}")));
+    let named_animations: bevy::utils::HashMap<Box<str>, Handle<AnimationClip>> = names
+        .map(|name| (name.into_boxed_str(), clip_assets.add(clip(targets))))
+        .collect();
+    Gltf {
+        scenes: default(),
+        named_scenes: default(),
+        meshes: default(),
+        named_meshes: default(),
+        materials: default(),
+        named_materials: default(),
+        nodes: default(),
+        named_nodes: default(),
+        default_scene: None,
+        animations: named_animations.values().cloned().collect(),
+        named_animations,
+        source: None,
+    }
+}
+
+fn app(names: &[Name]) -> App {
+    let targets: Vec<AnimationTargetId> = names.iter().map(AnimationTargetId::from_name).collect();
+    let mut clip_assets = Assets::<AnimationClip>::default();
+    let mut gltf_assets = Assets::<Gltf>::default();
+    let spider_gltf = gltf_assets.add(gltf(&targets, &mut clip_assets));
+
+    let mut app = App::new();
+    app.insert_resource(clip_assets)
+        .insert_resource(gltf_assets)
+        .insert_resource(Assets::<AnimationGraph>::default())
+        .insert_resource(Assets::<AnimNotifies>::default())
+        .insert_resource(MeshAssets {
+            spider_gltf,
+            ..default()
+        })
+        .add_systems(Update, init_animation_graph::<EnemyUnitAnim<Spider>>);
+    app
+}
+
+/// Spider rigs like the scene spawns them, the bones in a tree under the animation player
+fn spawn_units(world: &mut World, names: &[Name], units: usize) {
+    for _ in 0..units {
+        let player = world
+            .spawn((
+                EnemyUnitAnim::<Spider>::new(Entity::PLACEHOLDER),
+                AnimationPlayer::default(),
+                SpatialBundle::default(),
+            ))
+            .id();
+        let mut bones: Vec<Entity> = Vec::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            let parent = if i == 0 { player } else { bones[(i - 1) / 2] };
+            let bone = world
+                .spawn((
+                    name.clone(),
+                    AnimationTarget {
+                        id: AnimationTargetId::from_name(name),
+                        player,
+                    },
+                    SpatialBundle::default(),
+                ))
+                .set_parent(parent)
+                .id();
+            bones.push(bone);
+        }
+    }
+}
+
+/// Times the update that sets up `units` freshly spawned rigs
+fn timed_spawn(app: &mut App, names: &[Name], units: usize) -> Duration {
+    spawn_units(app.world_mut(), names, units);
+    let start = Instant::now();
+    app.update();
+    start.elapsed()
+}
+
+/// Makes sure the timed update did all the work, graph, root motion and layer bones included
+fn check_set_up(app: &mut App, units: usize) {
+    let world = app.world_mut();
+    let mut set_up = world.query_filtered::<(), (
+        With<AnimationIndices>,
+        With<Handle<AnimationGraph>>,
+        With<RootMotion>,
+        With<AdditiveLayerBones>,
+    )>();
+    assert_eq!(set_up.iter(world).count(), units);
+    assert_eq!(world.resource::<Assets<AnimationGraph>>().len(), 1);
+}
+
+/// Graph and index table built for every unit, how `init_animation_graph` worked before the cache
+fn per_instance(names: &[Name], units: usize) -> Duration {
+    let targets: Vec<AnimationTargetId> = names.iter().map(AnimationTargetId::from_name).collect();
+    let mut clip_assets = Assets::<AnimationClip>::default();
+    let mut graphs = Assets::<AnimationGraph>::default();
+    let gltf = gltf(&targets, &mut clip_assets);
+
+    let start = Instant::now();
+    let mut spawned: Vec<(Handle<AnimationGraph>, AnimationIndices)> = Vec::with_capacity(units);
+    for _ in 0..units {
+        let graph = SharedAnimGraph::build::<EnemyUnitAnim<Spider>>(
+            &gltf.named_animations,
+            None,
+            |_| targets[BONES - 1..].to_vec(),
+            &mut graphs,
+            &mut clip_assets,
+        );
+        spawned.push((graph.graph, graph.indices));
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let names = bone_names();
+
+    for units in [100, 300, 1000] {
+        let mut before = Duration::ZERO;
+        let mut cold = Duration::ZERO;
+        let mut warm = Duration::ZERO;
+        for _ in 0..RUNS {
+            before += per_instance(&names, units);
+
+            let mut app = app(&names);
+            cold += timed_spawn(&mut app, &names, units);
+            check_set_up(&mut app, units);
+            warm += timed_spawn(&mut app, &names, units);
+            check_set_up(&mut app, units * 2);
+        }
+        println!(
+            "{units:>5} units: per instance graphs {:>10.3?}, shared graph built {:>10.3?}, shared graph cached {:>10.3?}",
+            before / RUNS,
+            cold / RUNS,
+            warm / RUNS
+        );
+    }
+}
@@ -1,42 +1,1128 @@
-use std::time::Duration;
+use std::{
+    collections::VecDeque,
+    f32::consts::{PI, TAU},
+    sync::Arc,
+    time::Duration,
+};
 
-use bevy::{animation::ActiveAnimation, prelude::*, utils::hashbrown::HashMap};
+use bevy::{
+    animation::{
+        advance_animations, animate_targets, transition::advance_transitions, ActiveAnimation,
+        AnimationTarget, AnimationTargetId, Interpolation, Keyframes, RepeatAnimation,
+    },
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    core::FrameCount,
+    prelude::*,
+    reflect::TypePath,
+    render::primitives::{Frustum, Sphere},
+    transform::TransformSystem,
+    utils::hashbrown::HashMap,
+};
+use serde::Deserialize;
+use thiserror::Error;
 
-use crate::mesh_assets::MeshAssets;
+use crate::{fps_controller::RenderPlayer, mesh_assets::MeshAssets};
 
-#[derive(Component, Clone, Deref, DerefMut)]
-// TODO use enum or Handle<AnimationClip> or something once this is more figured out
-pub struct AnimationIndices(pub HashMap<String, AnimationNodeIndex>);
+pub struct AnimStateMachinePlugin;
+impl Plugin for AnimStateMachinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimStateMachine>()
+            .init_asset_loader::<AnimStateMachineLoader>()
+            .add_systems(
+                PostUpdate,
+                run_anim_state_machines.before(advance_transitions),
+            );
+    }
+}
+
+pub struct AnimNotifyPlugin;
+impl Plugin for AnimNotifyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimNotifies>()
+            .init_asset_loader::<AnimNotifiesLoader>()
+            .add_event::<AnimNotifyEvent>()
+            .add_systems(PostUpdate, emit_anim_notifies.after(animate_targets));
+    }
+}
 
+pub struct AnimBlendPlugin;
+impl Plugin for AnimBlendPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (
+                (update_blend_spaces, update_layers)
+                    .after(advance_transitions)
+                    .before(advance_animations),
+                apply_additive_layers
+                    .after(animate_targets)
+                    .before(lock_root_bones)
+                    .before(TransformSystem::TransformPropagate),
+            ),
+        );
+    }
+}
+
+pub struct RootMotionPlugin;
+impl Plugin for RootMotionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            lock_root_bones
+                .after(animate_targets)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+pub struct AnimLodPlugin;
+impl Plugin for AnimLodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AnimLodSettings>().add_systems(
+            PostUpdate,
+            (
+                update_anim_lod
+                    .after(update_blend_spaces)
+                    .after(update_layers)
+                    .before(advance_animations),
+                restore_anim_weights.after(animate_targets),
+            ),
+        );
+    }
+}
+
+/// Graph nodes of the clips, blend spaces and layers by name. Every player of an [`AnimClips`] type shares the
+/// same tables, see [`SharedAnimGraph`].
+#[derive(Component, Clone, Deref)]
+pub struct AnimationIndices(pub Arc<AnimTables>);
+
+#[derive(Deref)]
+pub struct AnimTables {
+    #[deref]
+    pub indices: HashMap<String, AnimationNodeIndex>,
+    pub notifies: HashMap<AnimationNodeIndex, ClipNotifies>,
+    /// By blend node
+    pub blend_spaces: HashMap<AnimationNodeIndex, BlendSpace>,
+    pub layers: HashMap<AnimationNodeIndex, AnimLayer>,
+    /// Clip of every clip node, the ones in blend spaces included
+    pub clips: HashMap<AnimationNodeIndex, Handle<AnimationClip>>,
+}
+
+impl AnimTables {
+    pub fn node(&self, name: impl AsRef<str>) -> Result<AnimationNodeIndex, AnimError> {
+        let name = name.as_ref();
+        self.get(name)
+            .copied()
+            .ok_or_else(|| AnimError::MissingClip(name.to_string()))
+    }
+}
+
+/// Animation setup of a glTF. Everything here is static so it can be checked against the glTF and the state
+/// machine when loading, see [`validate_anim_clips`]. Types that don't use some of the names can set them to
+/// [`NoAnimNames`].
 pub trait AnimClips {
-    fn get_gltf_id(&self, mesh_assets: &MeshAssets) -> Handle<Gltf>
-    where
-        Self: Sized;
+    /// Clips the code refers to
+    type Clip: AnimName;
+    /// Names of the blend spaces
+    type Blend: AnimName;
+    /// Names of the layers
+    type Layer: AnimName;
+    /// States the code checks for, the state machine has to have all of them
+    type State: AnimName;
+    /// Parameters the code sets, the state machine can't read any others
+    type Param: AnimName;
+    fn get_gltf_id(mesh_assets: &MeshAssets) -> Handle<Gltf>;
+    /// Timeline events for the clips of the glTF, usually a `.notifies.ron` next to it
+    fn get_notifies(_mesh_assets: &MeshAssets) -> Option<Handle<AnimNotifies>> {
+        None
+    }
+    /// Picks the clips from parameters set by gameplay code, if the clips aren't played by hand
+    fn get_state_machine(_mesh_assets: &MeshAssets) -> Option<Handle<AnimStateMachine>> {
+        None
+    }
+    fn get_blend_spaces() -> &'static [BlendSpaceDef<Self::Clip, Self::Blend>] {
+        &[]
+    }
+    fn get_layers() -> &'static [AnimLayerDef<Self::Clip, Self::Layer>] {
+        &[]
+    }
+}
+
+/// Clip, blend space, layer, state or parameter names as an enum, declared with
+/// [`anim_names!`](crate::anim_names)
+pub trait AnimName: AsRef<str> + Copy + PartialEq + Send + Sync + 'static {
+    const ALL: &'static [Self];
+    fn name(self) -> &'static str;
+}
+
+/// Declares an enum of animation names, with the name of each variant
+#[macro_export]
+macro_rules! anim_names {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($variant:ident = $clip:literal),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        $vis enum $name {
+            $($variant),*
+        }
+
+        impl $crate::animation::AnimName for $name {
+            const ALL: &'static [Self] = &[$($name::$variant),*];
+
+            fn name(self) -> &'static str {
+                match self {
+                    $($name::$variant => $clip),*
+                }
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                $crate::animation::AnimName::name(*self)
+            }
+        }
+    };
+}
+
+anim_names! {
+    /// For [`AnimClips`] types without blend spaces, layers or a state machine
+    pub enum NoAnimNames {}
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AnimError {
+    #[error("No clip, blend space or layer named {0}")]
+    MissingClip(String),
+    #[error("No blend space named {0}")]
+    MissingBlendSpace(String),
+    #[error("No layer named {0}")]
+    MissingLayer(String),
+    #[error("{gltf} is missing clips {missing:?}")]
+    MissingClips { gltf: String, missing: Vec<String> },
+    #[error("{gltf} is missing layer mask bones {missing:?}")]
+    MissingBones { gltf: String, missing: Vec<String> },
+    #[error("{machine} is missing states {missing:?}")]
+    MissingStates {
+        machine: String,
+        missing: Vec<String>,
+    },
+    #[error("{machine} reads parameters the code never sets {unknown:?}")]
+    UnknownParams {
+        machine: String,
+        unknown: Vec<String>,
+    },
+    #[error("Not loaded: {0}")]
+    NotLoaded(String),
+}
+
+/// Clips played together, weighted by where a parameter sits between them. Played by name like a clip, with
+/// [`AnimPlayerController::set_blend`] moving the parameter.
+#[derive(Clone, Copy, Debug)]
+pub struct BlendSpaceDef<C: 'static, N> {
+    pub name: N,
+    pub shape: BlendShape,
+    /// Clips and where they sit in parameter space
+    pub clips: &'static [(C, Vec2)],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendShape {
+    /// Along x, between the two clips either side of the parameter
+    Linear,
+    /// Inverse distance weighted, for clips spread over both axes
+    Planar,
+}
+
+/// Clip played over the others on part of the skeleton, e.g. a hit reaction on the upper body
+#[derive(Clone, Copy, Debug)]
+pub struct AnimLayerDef<C, N> {
+    pub name: N,
+    pub clip: C,
+    /// Bones whose subtrees the layer moves, empty for the whole skeleton
+    pub mask: &'static [&'static str],
+    pub blend: LayerBlend,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayerBlend {
+    /// Replaces the pose of the masked bones by the layer weight
+    Override,
+    /// Adds how far the clip moved from its first frame on top of the pose
+    Additive,
+}
+
+impl LayerBlend {
+    /// Weight of the layer's [`ActiveAnimation`]. Bevy averages the clips on a bone by weight and the base clips
+    /// add up to 1, so an override needs `w / (1 - w)` to make up `w` of the pose. Additive layers are kept out
+    /// of bevy's average and applied by [`apply_additive_layers`].
+    fn active_weight(self, weight: f32) -> f32 {
+        match self {
+            LayerBlend::Override => {
+                let weight = weight.clamp(0.0, 0.99);
+                weight / (1.0 - weight)
+            }
+            LayerBlend::Additive => 0.0,
+        }
+    }
+}
+
+/// Clips of a [`BlendSpaceDef`], under its blend node in the graph
+#[derive(Clone, Debug)]
+pub struct BlendSpace {
+    pub shape: BlendShape,
+    /// Graph node, position and duration of each clip. Sorted by x.
+    pub clips: Vec<(AnimationNodeIndex, Vec2, f32)>,
+}
+
+impl BlendSpace {
+    /// Weight of each clip, adding up to 1
+    pub fn weights(&self, position: Vec2) -> Vec<f32> {
+        let mut weights = vec![0.0; self.clips.len()];
+        if self.clips.is_empty() {
+            return weights;
+        }
+        match self.shape {
+            BlendShape::Linear => {
+                let next = self
+                    .clips
+                    .partition_point(|(_, pos, _)| pos.x <= position.x);
+                if next == 0 {
+                    weights[0] = 1.0;
+                } else if next == self.clips.len() {
+                    weights[next - 1] = 1.0;
+                } else {
+                    let (from, to) = (self.clips[next - 1].1.x, self.clips[next].1.x);
+                    let f = (position.x - from) / (to - from).max(f32::EPSILON);
+                    weights[next - 1] = 1.0 - f;
+                    weights[next] = f;
+                }
+            }
+            BlendShape::Planar => {
+                let dist_sq: Vec<f32> = self
+                    .clips
+                    .iter()
+                    .map(|(_, pos, _)| pos.distance_squared(position))
+                    .collect();
+                if let Some(exact) = dist_sq.iter().position(|d| *d < 1e-6) {
+                    weights[exact] = 1.0;
+                } else {
+                    let total: f32 = dist_sq.iter().map(|d| 1.0 / d).sum();
+                    for (weight, d) in weights.iter_mut().zip(&dist_sq) {
+                        *weight = 1.0 / d / total;
+                    }
+                }
+            }
+        }
+        weights
+    }
+}
+
+/// An [`AnimLayerDef`] in the graph
+#[derive(Clone, Debug)]
+pub struct AnimLayer {
+    pub blend: LayerBlend,
+    pub clip: Handle<AnimationClip>,
+    /// Masked targets the clip animates, only kept for additive layers which are applied by hand
+    targets: Vec<AnimationTargetId>,
+}
+
+/// Bones of each additive layer of one player, [`apply_additive_layers`] moves them
+#[derive(Component, Clone, Default)]
+pub struct AdditiveLayerBones(HashMap<AnimationNodeIndex, Vec<(Entity, AnimationTargetId)>>);
+
+impl AdditiveLayerBones {
+    /// None if there are no additive layers
+    fn find(
+        player_entity: Entity,
+        indices: &AnimationIndices,
+        children: &Query<&Children>,
+        names: &Query<&Name>,
+        bones: &Query<(Option<&AnimationTarget>, &Transform)>,
+    ) -> Option<Self> {
+        if !indices
+            .layers
+            .values()
+            .any(|layer| layer.blend == LayerBlend::Additive)
+        {
+            return None;
+        }
+        let by_target: HashMap<AnimationTargetId, Entity> =
+            masked_targets(player_entity, &[], children, names, bones)
+                .into_iter()
+                .map(|(entity, target)| (target, entity))
+                .collect();
+        Some(AdditiveLayerBones(
+            indices
+                .layers
+                .iter()
+                .filter(|(_, layer)| layer.blend == LayerBlend::Additive)
+                .map(|(idx, layer)| {
+                    let layer_bones = layer
+                        .targets
+                        .iter()
+                        .filter_map(|target| Some((*by_target.get(target)?, *target)))
+                        .collect();
+                    (*idx, layer_bones)
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Inputs of the blend spaces and layers of one animation player, set through [`AnimPlayerController`]
+#[derive(Component, Clone, Default)]
+pub struct AnimParams {
+    blend: HashMap<AnimationNodeIndex, BlendState>,
+    /// Layers show fully until their weight is set
+    layers: HashMap<AnimationNodeIndex, f32>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct BlendState {
+    position: Vec2,
+    /// Normalized time shared by the clips, so feet line up while blending
+    phase: f32,
+    prev_phase: f32,
+}
+
+/// Named points on clip timelines, by clip name. Loaded from `.notifies.ron` files.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct AnimNotifies(pub bevy::utils::HashMap<String, Vec<AnimNotify>>);
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimNotify {
+    pub name: String,
+    /// Seconds from the start of the clip, at speed 1. Ignored for `at_end` notifies.
+    #[serde(default)]
+    pub time: f32,
+    /// Fires when the clip finishes, or each time it wraps around if it repeats
+    #[serde(default)]
+    pub at_end: bool,
+}
+
+/// Notifies of one clip, sorted by time
+#[derive(Clone, Debug, Default)]
+pub struct ClipNotifies {
+    pub duration: f32,
+    pub notifies: Vec<AnimNotify>,
+}
+
+impl ClipNotifies {
+    /// Notifies passed when the clip moved from `from` to `to`. Includes `to` but not `from`.
+    pub fn between(&self, from: f32, to: f32) -> impl Iterator<Item = &AnimNotify> {
+        self.notifies
+            .iter()
+            .filter(move |notify| notify.time > from && notify.time <= to)
+    }
+}
+
+/// Sent when a playing clip passes a notify on its timeline
+#[derive(Event, Clone, Debug)]
+pub struct AnimNotifyEvent {
+    /// Entity with the [`AnimationPlayer`]
+    pub entity: Entity,
+    pub clip: AnimationNodeIndex,
+    pub name: String,
+}
+
+/// Seek time and completions of each playing clip when notifies were last emitted
+#[derive(Component, Clone, Default)]
+pub struct AnimNotifyCursor(HashMap<AnimationNodeIndex, (f32, u32)>);
+
+/// Graph and lookup tables of one [`AnimClips`] type, built for the first player that needs them and shared by
+/// the rest
+#[derive(Clone)]
+pub struct SharedAnimGraph {
+    pub graph: Handle<AnimationGraph>,
+    pub indices: AnimationIndices,
+    root_target: Option<AnimationTargetId>,
+}
+
+impl SharedAnimGraph {
+    /// `mask_targets` gives the animation targets in the subtrees of the named bones, or all of them for an
+    /// empty mask
+    pub fn build<T: AnimClips>(
+        named_animations: &bevy::utils::HashMap<Box<str>, Handle<AnimationClip>>,
+        notifies: Option<&AnimNotifies>,
+        mask_targets: impl Fn(&[&str]) -> Vec<AnimationTargetId>,
+        graphs: &mut Assets<AnimationGraph>,
+        clip_assets: &mut Assets<AnimationClip>,
+    ) -> Self {
+        let mut anim_indices = HashMap::new();
+        let mut anim_notifies = HashMap::new();
+        let mut clips = HashMap::new();
+        let mut animation_graph = AnimationGraph::new();
+        for (name, clip_handle) in named_animations.iter() {
+            let idx = animation_graph.add_clip(clip_handle.clone(), 1.0, animation_graph.root);
+            anim_indices.insert(name.to_string(), idx);
+            clips.insert(idx, clip_handle.clone());
+            if let Some(clip_notifies) = notifies.and_then(|n| n.0.get(&**name)) {
+                let duration = clip_assets.get(clip_handle).map_or(0.0, |c| c.duration());
+                let mut clip_notifies = clip_notifies.clone();
+                for notify in &mut clip_notifies {
+                    if notify.at_end {
+                        notify.time = duration;
+                    } else if notify.time > duration {
+                        warn!(
+                            "Notify {} at {}s is past the end of clip {}, use at_end instead",
+                            notify.name, notify.time, name
+                        );
+                        notify.time = duration;
+                    }
+                }
+                clip_notifies.sort_by(|a, b| a.time.total_cmp(&b.time));
+                anim_notifies.insert(
+                    idx,
+                    ClipNotifies {
+                        duration,
+                        notifies: clip_notifies,
+                    },
+                );
+            }
+        }
+
+        let mut blend_spaces = HashMap::new();
+        for def in T::get_blend_spaces() {
+            let node = animation_graph.add_blend(1.0, animation_graph.root);
+            let mut space_clips = Vec::new();
+            for (clip, position) in def.clips {
+                // Missing clips were reported by validate_anim_clips
+                let Some(clip_handle) = named_animations.get(clip.name()) else {
+                    continue;
+                };
+                let idx = animation_graph.add_clip(clip_handle.clone(), 1.0, node);
+                let duration = clip_assets.get(clip_handle).map_or(0.0, |c| c.duration());
+                clips.insert(idx, clip_handle.clone());
+                space_clips.push((idx, *position, duration));
+            }
+            space_clips.sort_by(|a, b| a.1.x.total_cmp(&b.1.x));
+            anim_indices.insert(def.name.name().to_string(), node);
+            blend_spaces.insert(
+                node,
+                BlendSpace {
+                    shape: def.shape,
+                    clips: space_clips,
+                },
+            );
+        }
+
+        let mut layers = HashMap::new();
+        for def in T::get_layers() {
+            let Some(source) = named_animations.get(def.clip.name()) else {
+                continue;
+            };
+            let Some(source_clip) = clip_assets.get(source) else {
+                continue;
+            };
+            let targets: Vec<AnimationTargetId> = mask_targets(def.mask)
+                .into_iter()
+                .filter(|target| source_clip.curves_for_target(*target).is_some())
+                .collect();
+            if targets.is_empty() {
+                warn!(
+                    "Layer {} doesn't animate any bones of its mask {:?}",
+                    def.name.name(),
+                    def.mask
+                );
+            }
+            let (clip, layer_targets) = match def.blend {
+                // Bevy applies every curve of a clip, so the override gets a copy with just the masked bones
+                LayerBlend::Override if !def.mask.is_empty() => {
+                    let mut masked = AnimationClip::default();
+                    for target in &targets {
+                        for curve in source_clip.curves_for_target(*target).into_iter().flatten() {
+                            masked.add_curve_to_target(*target, curve.clone());
+                        }
+                    }
+                    masked.set_duration(source_clip.duration());
+                    (clip_assets.add(masked), Vec::new())
+                }
+                LayerBlend::Override => (source.clone(), Vec::new()),
+                LayerBlend::Additive => (source.clone(), targets),
+            };
+            let idx = animation_graph.add_clip(clip.clone(), 1.0, animation_graph.root);
+            anim_indices.insert(def.name.name().to_string(), idx);
+            layers.insert(
+                idx,
+                AnimLayer {
+                    blend: def.blend,
+                    clip,
+                    targets: layer_targets,
+                },
+            );
+        }
+
+        SharedAnimGraph {
+            graph: graphs.add(animation_graph),
+            indices: AnimationIndices(Arc::new(AnimTables {
+                indices: anim_indices,
+                notifies: anim_notifies,
+                blend_spaces,
+                layers,
+                clips,
+            })),
+            root_target: None,
+        }
+    }
+}
+
+/// [`SharedAnimGraph`] by glTF. Each [`init_animation_graph`] system keeps its own, so it's also per
+/// [`AnimClips`] type.
+#[derive(Default)]
+pub struct AnimGraphCache(HashMap<AssetId<Gltf>, SharedAnimGraph>);
+
+impl AnimGraphCache {
+    pub fn get_or_build(
+        &mut self,
+        gltf: AssetId<Gltf>,
+        build: impl FnOnce() -> SharedAnimGraph,
+    ) -> SharedAnimGraph {
+        self.0.entry(gltf).or_insert_with(build).clone()
+    }
 }
 
 pub fn init_animation_graph<T: AnimClips + Component>(
     mut commands: Commands,
-    mut players: Query<(Entity, &T), Added<AnimationPlayer>>,
+    players: Query<Entity, (With<T>, Added<AnimationPlayer>)>,
+    mut cache: Local<AnimGraphCache>,
     mut animation_graphs: ResMut<Assets<AnimationGraph>>,
-    gltf_assets: ResMut<Assets<Gltf>>,
+    gltf_assets: Res<Assets<Gltf>>,
+    mut clip_assets: ResMut<Assets<AnimationClip>>,
+    notify_assets: Res<Assets<AnimNotifies>>,
     mesh_assets: Res<MeshAssets>,
+    hierarchy: (Query<&Children>, Query<&Parent>),
+    bones: Query<(Option<&AnimationTarget>, &Transform)>,
+    names: Query<&Name>,
 ) {
-    for (entity, anim) in &mut players {
-        let mut anim_indices = HashMap::new();
-        let mut animation_graph = AnimationGraph::new();
-        let gltf = gltf_assets.get(&anim.get_gltf_id(&mesh_assets)).unwrap();
-        for (name, clip_handle) in gltf.named_animations.iter() {
-            anim_indices.insert(
-                name.to_string(),
-                animation_graph.add_clip(clip_handle.clone(), 1.0, animation_graph.root),
+    for entity in &players {
+        let gltf_handle = T::get_gltf_id(&mesh_assets);
+        let Some(gltf) = gltf_assets.get(&gltf_handle) else {
+            warn_once!("glTF for {} isn't loaded", std::any::type_name::<T>());
+            continue;
+        };
+        let shared = cache.get_or_build(gltf_handle.id(), || {
+            let notifies =
+                T::get_notifies(&mesh_assets).and_then(|handle| notify_assets.get(&handle));
+            let mut shared = SharedAnimGraph::build::<T>(
+                &gltf.named_animations,
+                notifies,
+                |mask| {
+                    masked_targets(entity, mask, &hierarchy.0, &names, &bones)
+                        .into_iter()
+                        .map(|(_, target)| target)
+                        .collect()
+                },
+                &mut animation_graphs,
+                &mut clip_assets,
+            );
+            shared.root_target = RootMotion::root_target(
+                entity,
+                &shared.indices.clips,
+                &clip_assets,
+                &hierarchy.0,
+                &bones,
             );
+            shared
+        });
+
+        let mut ecmds = commands.entity(entity);
+        ecmds.insert((
+            AnimationTransitions::new(),
+            shared.graph.clone(),
+            shared.indices.clone(),
+            AnimNotifyCursor::default(),
+            AnimParams::default(),
+            AnimLod::default(),
+        ));
+        if let Some(layer_bones) =
+            AdditiveLayerBones::find(entity, &shared.indices, &hierarchy.0, &names, &bones)
+        {
+            ecmds.insert(layer_bones);
+        }
+        if let Some(root_motion) = shared.root_target.and_then(|root_target| {
+            RootMotion::find(entity, root_target, shared.indices, &hierarchy, &bones)
+        }) {
+            ecmds.insert(root_motion);
+        }
+        if let Some(machine) = T::get_state_machine(&mesh_assets) {
+            ecmds.insert(AnimStateMachinePlayer::new(machine));
+        }
+    }
+}
+
+/// Runs [`check_anim_clips`] when leaving `GameLoading::AssetLoading2`, once the assets are in. Pipe it into
+/// something that logs the error and keeps the animations from playing, they wouldn't play right.
+pub fn validate_anim_clips<T: AnimClips>(
+    gltf_assets: Res<Assets<Gltf>>,
+    notify_assets: Res<Assets<AnimNotifies>>,
+    machines: Res<Assets<AnimStateMachine>>,
+    mesh_assets: Res<MeshAssets>,
+    asset_server: Res<AssetServer>,
+) -> Result<(), AnimError> {
+    check_anim_clips::<T>(
+        &gltf_assets,
+        &notify_assets,
+        &machines,
+        &mesh_assets,
+        &asset_server,
+    )
+}
+
+/// Every clip of [`AnimClips::Clip`], of the state machine states and of the notifies has to be in the glTF, and
+/// so do the bones of the layer masks. The state machine has to have every [`AnimClips::State`] and only read
+/// [`AnimClips::Param`]s.
+pub fn check_anim_clips<T: AnimClips>(
+    gltf_assets: &Assets<Gltf>,
+    notify_assets: &Assets<AnimNotifies>,
+    machines: &Assets<AnimStateMachine>,
+    mesh_assets: &MeshAssets,
+    asset_server: &AssetServer,
+) -> Result<(), AnimError> {
+    let gltf_handle = T::get_gltf_id(mesh_assets);
+    let gltf = gltf_assets
+        .get(&gltf_handle)
+        .ok_or_else(|| AnimError::NotLoaded(asset_name(asset_server, &gltf_handle)))?;
+
+    let mut referenced: Vec<String> = T::Clip::ALL
+        .iter()
+        .map(|clip| clip.name().to_string())
+        .collect();
+    if let Some(handle) = T::get_state_machine(mesh_assets) {
+        let machine = machines
+            .get(&handle)
+            .ok_or_else(|| AnimError::NotLoaded(asset_name(asset_server, &handle)))?;
+        // Blend spaces and layers are played by their own names
+        let own_names: Vec<&str> = T::get_blend_spaces()
+            .iter()
+            .map(|def| def.name.name())
+            .chain(T::get_layers().iter().map(|def| def.name.name()))
+            .collect();
+        referenced.extend(
+            machine
+                .states
+                .values()
+                .map(|state| state.clip.clone())
+                .filter(|clip| !own_names.contains(&clip.as_str())),
+        );
+
+        let mut missing: Vec<String> = T::State::ALL
+            .iter()
+            .filter(|state| !machine.states.contains_key(state.name()))
+            .map(|state| state.name().to_string())
+            .collect();
+        if !missing.is_empty() {
+            missing.sort();
+            return Err(AnimError::MissingStates {
+                machine: asset_name(asset_server, &handle),
+                missing,
+            });
         }
+        let params: Vec<&str> = T::Param::ALL.iter().map(|param| param.name()).collect();
+        let mut unknown: Vec<String> = machine
+            .params()
+            .filter(|param| !params.contains(param))
+            .map(|param| param.to_string())
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            return Err(AnimError::UnknownParams {
+                machine: asset_name(asset_server, &handle),
+                unknown,
+            });
+        }
+    }
+    if let Some(handle) = T::get_notifies(mesh_assets) {
+        let notifies = notify_assets
+            .get(&handle)
+            .ok_or_else(|| AnimError::NotLoaded(asset_name(asset_server, &handle)))?;
+        referenced.extend(notifies.0.keys().cloned());
+    }
+
+    let mut missing: Vec<String> = referenced
+        .into_iter()
+        .filter(|clip| !gltf.named_animations.contains_key(clip.as_str()))
+        .collect();
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        return Err(AnimError::MissingClips {
+            gltf: asset_name(asset_server, &gltf_handle),
+            missing,
+        });
+    }
 
-        let graph_handle = animation_graphs.add(animation_graph);
-        let transitions = AnimationTransitions::new();
-        commands
-            .entity(entity)
-            .insert((transitions, graph_handle, AnimationIndices(anim_indices)));
+    // A mask bone that isn't there leaves its layer without any bones
+    let mut missing: Vec<String> = T::get_layers()
+        .iter()
+        .flat_map(|def| def.mask.iter())
+        .filter(|bone| !gltf.named_nodes.contains_key(**bone))
+        .map(|bone| bone.to_string())
+        .collect();
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        return Err(AnimError::MissingBones {
+            gltf: asset_name(asset_server, &gltf_handle),
+            missing,
+        });
+    }
+    Ok(())
+}
+
+/// Path of an asset for error messages, the handles in [`MeshAssets`] used here can be weak and have none
+fn asset_name<A: Asset>(asset_server: &AssetServer, handle: &Handle<A>) -> String {
+    asset_server
+        .get_path(handle.id())
+        .map_or_else(|| format!("{:?}", handle.id()), |path| path.to_string())
+}
+
+/// Animated bones under the player, only those in the subtrees of the named bones if there are any
+fn masked_targets(
+    player_entity: Entity,
+    mask: &[&str],
+    children: &Query<&Children>,
+    names: &Query<&Name>,
+    bones: &Query<(Option<&AnimationTarget>, &Transform)>,
+) -> Vec<(Entity, AnimationTargetId)> {
+    let mut targets = Vec::new();
+    let mut stack = vec![(player_entity, mask.is_empty())];
+    while let Some((entity, parent_masked)) = stack.pop() {
+        let masked = parent_masked
+            || names
+                .get(entity)
+                .is_ok_and(|name| mask.contains(&name.as_str()));
+        if let Ok((Some(target), _)) = bones.get(entity) {
+            if masked {
+                targets.push((entity, target.id));
+            }
+        }
+        if let Ok(entity_children) = children.get(entity) {
+            stack.extend(entity_children.iter().map(|child| (*child, masked)));
+        }
+    }
+    targets
+}
+
+/// Shallowest animated bone of a skeleton. Its horizontal translation and its rotation around the up axis are
+/// taken out of the clips and applied to the unit instead. Units only take the speed of the translation and keep
+/// walking where they steer, so the feet match for clips that walk straight ahead but sidesteps still slide.
+#[derive(Component, Clone)]
+pub struct RootMotion {
+    pub bone: Entity,
+    target: AnimationTargetId,
+    /// Bone parent space to animation player space, without translation
+    to_player: Mat3,
+    /// Animation player up axis in bone parent space
+    up: Vec3,
+    rest_translation: Vec3,
+    rest_yaw: f32,
+    indices: AnimationIndices,
+}
+
+impl RootMotion {
+    /// Shallowest animation target that a clip moves or turns. The same for every instance of a glTF, so
+    /// it's kept in [`SharedAnimGraph`].
+    fn root_target(
+        player_entity: Entity,
+        clips: &HashMap<AnimationNodeIndex, Handle<AnimationClip>>,
+        clip_assets: &Assets<AnimationClip>,
+        children: &Query<&Children>,
+        bones: &Query<(Option<&AnimationTarget>, &Transform)>,
+    ) -> Option<AnimationTargetId> {
+        let moves_root = |target: AnimationTargetId| {
+            clips
+                .values()
+                .filter_map(|h| clip_assets.get(h))
+                .any(|clip| {
+                    clip.curves_for_target(target).is_some_and(|curves| {
+                        curves.iter().any(|curve| {
+                            matches!(
+                                curve.keyframes,
+                                Keyframes::Translation(_) | Keyframes::Rotation(_)
+                            )
+                        })
+                    })
+                })
+        };
+        // Breadth first, so the first match is the shallowest
+        let mut queue = VecDeque::from([player_entity]);
+        std::iter::from_fn(|| {
+            let entity = queue.pop_front()?;
+            if let Ok(entity_children) = children.get(entity) {
+                queue.extend(entity_children.iter());
+            }
+            Some(entity)
+        })
+        .filter_map(|entity| bones.get(entity).ok()?.0.map(|target| target.id))
+        .find(|target| moves_root(*target))
+    }
+
+    /// Finds the bone of `root_target` under this player
+    fn find(
+        player_entity: Entity,
+        root_target: AnimationTargetId,
+        indices: AnimationIndices,
+        (children, parents): &(Query<&Children>, Query<&Parent>),
+        bones: &Query<(Option<&AnimationTarget>, &Transform)>,
+    ) -> Option<Self> {
+        let mut queue = VecDeque::from([player_entity]);
+        let bone = std::iter::from_fn(|| {
+            let entity = queue.pop_front()?;
+            if let Ok(entity_children) = children.get(entity) {
+                queue.extend(entity_children.iter());
+            }
+            Some(entity)
+        })
+        .find(|entity| {
+            bones
+                .get(*entity)
+                .is_ok_and(|(target, _)| target.is_some_and(|target| target.id == root_target))
+        })?;
+        let (_, rest) = bones.get(bone).ok()?;
+
+        // Local transforms are used since global ones aren't propagated yet when the scene spawns
+        let mut to_player = Mat3::IDENTITY;
+        let mut entity = parents.get(bone).ok()?.get();
+        while entity != player_entity {
+            let trans = bones
+                .get(entity)
+                .map_or(Transform::IDENTITY, |(_, trans)| *trans);
+            to_player =
+                Mat3::from_quat(trans.rotation) * Mat3::from_diagonal(trans.scale) * to_player;
+            entity = parents.get(entity).ok()?.get();
+        }
+        let up = (to_player.inverse() * Vec3::Y).normalize_or(Vec3::Y);
+        Some(RootMotion {
+            bone,
+            target: root_target,
+            to_player,
+            up,
+            rest_translation: rest.translation,
+            rest_yaw: twist_angle(rest.rotation, up),
+            indices,
+        })
+    }
+
+    /// Horizontal root bone movement in animation player space and turn in radians around the up axis, between
+    /// two seek times of a clip. Wraps around the end of the clip if `to` is before `from`.
+    pub fn delta(
+        &self,
+        clip_assets: &Assets<AnimationClip>,
+        idx: AnimationNodeIndex,
+        from: f32,
+        to: f32,
+    ) -> Option<(Vec3, f32)> {
+        let clip = clip_assets.get(self.indices.clips.get(&idx)?)?;
+        let curves = clip.curves_for_target(self.target)?;
+        let duration = clip.duration();
+        let from = if from < 0.0 { from + duration } else { from };
+        let segment = |a: f32, b: f32| {
+            let mut translation = Vec3::ZERO;
+            let mut yaw = 0.0;
+            for curve in curves {
+                match &curve.keyframes {
+                    Keyframes::Translation(values) => {
+                        translation += sample_curve(curve, values, b, Vec3::lerp)
+                            - sample_curve(curve, values, a, Vec3::lerp);
+                    }
+                    Keyframes::Rotation(values) => {
+                        let end = twist_angle(sample_curve(curve, values, b, Quat::slerp), self.up);
+                        let start =
+                            twist_angle(sample_curve(curve, values, a, Quat::slerp), self.up);
+                        yaw += wrap_angle(end - start);
+                    }
+                    _ => (),
+                }
+            }
+            (translation, yaw)
+        };
+        let (translation, yaw) = if to >= from {
+            segment(from, to)
+        } else {
+            let (end_translation, end_yaw) = segment(from, duration);
+            let (start_translation, start_yaw) = segment(0.0, to);
+            (end_translation + start_translation, end_yaw + start_yaw)
+        };
+        let mut translation = self.to_player * translation;
+        translation.y = 0.0;
+        Some((translation, yaw))
+    }
+}
+
+/// Value of a translation or rotation curve at `t`, clamped to its first and last keyframes
+fn sample_curve<V: Copy>(
+    curve: &VariableCurve,
+    values: &[V],
+    t: f32,
+    lerp: fn(V, V, f32) -> V,
+) -> V {
+    let times = &curve.keyframe_timestamps;
+    // Cubic spline keyframes are stored as in tangent, value, out tangent
+    let value = |i: usize| match curve.interpolation {
+        Interpolation::CubicSpline => values[i * 3 + 1],
+        _ => values[i],
+    };
+    let next = times.partition_point(|time| *time <= t);
+    if next == 0 {
+        return value(0);
+    }
+    if next == times.len() {
+        return value(times.len() - 1);
+    }
+    let prev = next - 1;
+    match curve.interpolation {
+        Interpolation::Step => value(prev),
+        _ => {
+            let f = (t - times[prev]) / (times[next] - times[prev]).max(f32::EPSILON);
+            lerp(value(prev), value(next), f)
+        }
+    }
+}
+
+/// Angle of the part of `rotation` that turns around `axis`
+fn twist_angle(rotation: Quat, axis: Vec3) -> f32 {
+    2.0 * rotation.xyz().dot(axis).atan2(rotation.w)
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// Keeps the root bone in place after the clips are applied, [`RootMotion::delta`] moves the unit instead
+fn lock_root_bones(
+    roots: Query<(&RootMotion, Option<&AnimLod>)>,
+    mut bones: Query<&mut Transform>,
+) {
+    for (root, lod) in &roots {
+        // Skipped poses aren't written, the bone still has the one locked on the last sampled frame
+        if !AnimLod::sampled(lod) {
+            continue;
+        }
+        let Ok(mut trans) = bones.get_mut(root.bone) else {
+            continue;
+        };
+        let offset = trans.translation - root.rest_translation;
+        trans.translation = root.rest_translation + root.up * offset.dot(root.up);
+        let yaw = twist_angle(trans.rotation, root.up);
+        trans.rotation = Quat::from_axis_angle(root.up, root.rest_yaw - yaw) * trans.rotation;
+    }
+}
+
+/// Distances from the [`RenderPlayer`] past which unit poses are sampled less often, see [`AnimLod`]
+#[derive(Resource, Clone)]
+pub struct AnimLodSettings {
+    /// (distance, interval) sorted by distance. Players past the distance are sampled every `interval` frames.
+    pub levels: Vec<(f32, u32)>,
+    /// Players further than this keep their last pose
+    pub freeze_distance: f32,
+    /// Interval for players outside the camera frustum, 0 keeps their last pose. Their shadows can still be seen.
+    pub offscreen_interval: u32,
+    /// Radius around the animation player that's tested against the frustum
+    pub bounds_radius: f32,
+}
+
+impl Default for AnimLodSettings {
+    fn default() -> Self {
+        Self {
+            levels: vec![(30.0, 2), (60.0, 4)],
+            freeze_distance: 150.0,
+            offscreen_interval: 6,
+            bounds_radius: 4.0,
+        }
+    }
+}
+
+/// How often the pose of an animation player is sampled. Only the pose is throttled: clip time still advances
+/// every frame, so finishing clips, notifies and root motion keep their timing.
+#[derive(Component, Clone)]
+pub struct AnimLod {
+    /// Sample every `interval` frames, 0 never
+    pub interval: u32,
+    /// The pose isn't sampled this frame
+    skipped: bool,
+    /// A pose was sampled before, so there's one to keep instead of the rest pose
+    posed: bool,
+    /// Weights of the active animations, zeroed from before bevy advances them until it has sampled the poses
+    hidden_weights: Vec<(AnimationNodeIndex, f32)>,
+}
+
+impl Default for AnimLod {
+    fn default() -> Self {
+        Self {
+            interval: 1,
+            skipped: false,
+            posed: false,
+            hidden_weights: Vec::new(),
+        }
+    }
+}
+
+impl AnimLod {
+    /// True if the pose of `lod`'s player was sampled this frame
+    pub fn sampled(lod: Option<&AnimLod>) -> bool {
+        lod.map_or(true, |lod| !lod.skipped)
+    }
+}
+
+fn update_anim_lod(
+    mut players: Query<(Entity, &GlobalTransform, &mut AnimLod, &mut AnimationPlayer)>,
+    camera: Query<(&GlobalTransform, &Frustum), With<RenderPlayer>>,
+    settings: Res<AnimLodSettings>,
+    frame: Res<FrameCount>,
+) {
+    let camera = camera.get_single().ok();
+    for (entity, trans, mut lod, mut player) in &mut players {
+        let pos = trans.translation();
+        lod.interval = match camera {
+            None => 1,
+            Some((camera_trans, frustum)) => {
+                let dist = pos.distance(camera_trans.translation());
+                let sphere = Sphere {
+                    center: pos.into(),
+                    radius: settings.bounds_radius,
+                };
+                if dist > settings.freeze_distance {
+                    0
+                } else if !frustum.intersects_sphere(&sphere, true) {
+                    settings.offscreen_interval
+                } else {
+                    settings
+                        .levels
+                        .iter()
+                        .rev()
+                        .find(|(level_dist, _)| dist > *level_dist)
+                        .map_or(1, |(_, interval)| *interval)
+                }
+            }
+        };
+        // Offset by entity so throttled players don't all sample on the same frame
+        lod.skipped = lod.posed
+            && lod.interval != 1
+            && (lod.interval == 0 || frame.0.wrapping_add(entity.index()) % lod.interval != 0);
+        if !lod.skipped {
+            lod.posed = true;
+            continue;
+        }
+        // advance_animations still moves clip time on at weight 0, but computes a weight of 0 for animate_targets
+        // to skip them by. Blend space and layer weights are already set for this frame.
+        let AnimLod { hidden_weights, .. } = &mut *lod;
+        for (idx, anim) in player.playing_animations_mut() {
+            if anim.weight() != 0.0 {
+                hidden_weights.push((*idx, anim.weight()));
+                anim.set_weight(0.0);
+            }
+        }
+    }
+}
+
+fn restore_anim_weights(mut players: Query<(&mut AnimLod, &mut AnimationPlayer)>) {
+    for (mut lod, mut player) in &mut players {
+        for (idx, weight) in lod.hidden_weights.drain(..) {
+            if let Some(anim) = player.animation_mut(idx) {
+                anim.set_weight(weight);
+            }
+        }
     }
 }
 
@@ -45,6 +1131,7 @@ pub struct AnimPlayerController<'a> {
     pub transitions: &'a mut AnimationTransitions,
     pub player: &'a mut AnimationPlayer,
     pub anim: &'a AnimationIndices,
+    pub params: &'a mut AnimParams,
 }
 
 impl<'a> AnimPlayerController<'a> {
@@ -52,24 +1139,26 @@ impl<'a> AnimPlayerController<'a> {
         transitions: &'a mut AnimationTransitions,
         player: &'a mut AnimationPlayer,
         anim: &'a AnimationIndices,
+        params: &'a mut AnimParams,
     ) -> Self {
         AnimPlayerController {
             transitions,
             player,
             anim,
+            params,
         }
     }
 
-    pub fn play(&mut self, name: &str, transition: f32, speed: f32, repeat: bool) {
-        self.play_idx(
-            *self
-                .anim
-                .get(name)
-                .unwrap_or_else(|| panic!("{}", self.no_name_msg(name))),
-            transition,
-            speed,
-            repeat,
-        )
+    pub fn play(
+        &mut self,
+        clip: impl AsRef<str>,
+        transition: f32,
+        speed: f32,
+        repeat: bool,
+    ) -> Result<(), AnimError> {
+        let idx = self.anim.node(clip)?;
+        self.play_idx(idx, transition, speed, repeat);
+        Ok(())
     }
 
     pub fn play_idx(&mut self, idx: AnimationNodeIndex, transition: f32, speed: f32, repeat: bool) {
@@ -82,36 +1171,147 @@ impl<'a> AnimPlayerController<'a> {
         }
     }
 
-    pub fn playing(&mut self, name: &str) -> bool {
-        self.player.is_playing_animation(
-            *self
-                .anim
-                .get(name)
-                .unwrap_or_else(|| panic!("{}", self.no_name_msg(name))),
-        )
+    pub fn playing(&mut self, clip: impl AsRef<str>) -> Result<bool, AnimError> {
+        Ok(self.player.is_playing_animation(self.anim.node(clip)?))
     }
 
-    fn no_name_msg(&self, name: &str) -> String {
-        format!(
-            "Couldn't find animation with name: {}. Options: {:?}",
-            name,
-            self.anim.iter().map(|(_, v)| v)
-        )
+    /// Playing and not paused with a speed of 0
+    pub fn advancing(&mut self, clip: impl AsRef<str>) -> Result<bool, AnimError> {
+        Ok(self
+            .animation(clip)?
+            .is_some_and(|anim| anim.speed() != 0.0))
+    }
+
+    /// Changes the speed of a clip that is already playing
+    pub fn set_speed(&mut self, clip: impl AsRef<str>, speed: f32) -> Result<(), AnimError> {
+        if let Some(anim) = self.player.animation_mut(self.anim.node(clip)?) {
+            anim.set_speed(speed);
+        }
+        Ok(())
+    }
+
+    /// Moves the parameter of a blend space
+    pub fn set_blend(&mut self, name: impl AsRef<str>, position: Vec2) -> Result<(), AnimError> {
+        let idx = self.blend_space_node(name)?;
+        self.params.blend.entry(idx).or_default().position = position;
+        Ok(())
+    }
+
+    pub fn set_blend_1d(&mut self, name: impl AsRef<str>, x: f32) -> Result<(), AnimError> {
+        self.set_blend(name, Vec2::new(x, 0.0))
+    }
+
+    /// Restarts a layer on top of whatever else is playing
+    pub fn play_layer(
+        &mut self,
+        name: impl AsRef<str>,
+        speed: f32,
+        repeat: bool,
+    ) -> Result<(), AnimError> {
+        let idx = self.layer_node(name)?;
+        let blend = self.anim.layers[&idx].blend;
+        let weight = self.params.layers.get(&idx).copied().unwrap_or(1.0);
+        let anim = self.player.start(idx);
+        anim.set_speed(speed).set_repeat(if repeat {
+            RepeatAnimation::Forever
+        } else {
+            RepeatAnimation::Never
+        });
+        anim.set_weight(blend.active_weight(weight));
+        Ok(())
+    }
+
+    pub fn stop_layer(&mut self, name: impl AsRef<str>) -> Result<(), AnimError> {
+        let idx = self.layer_node(name)?;
+        self.player.stop(idx);
+        Ok(())
+    }
+
+    /// How much of a layer shows, from 0 to 1
+    pub fn set_layer_weight(
+        &mut self,
+        name: impl AsRef<str>,
+        weight: f32,
+    ) -> Result<(), AnimError> {
+        let idx = self.layer_node(name)?;
+        self.params.layers.insert(idx, weight);
+        Ok(())
+    }
+
+    fn blend_space_node(&self, name: impl AsRef<str>) -> Result<AnimationNodeIndex, AnimError> {
+        let name = name.as_ref();
+        self.anim
+            .get(name)
+            .copied()
+            .filter(|idx| self.anim.blend_spaces.contains_key(idx))
+            .ok_or_else(|| AnimError::MissingBlendSpace(name.to_string()))
+    }
+
+    fn layer_node(&self, name: impl AsRef<str>) -> Result<AnimationNodeIndex, AnimError> {
+        let name = name.as_ref();
+        self.anim
+            .get(name)
+            .copied()
+            .filter(|idx| self.anim.layers.contains_key(idx))
+            .ok_or_else(|| AnimError::MissingLayer(name.to_string()))
+    }
+
+    /// Root motion of a playing clip over the last `dt` seconds, see [`RootMotion::delta`]. Blend spaces give the
+    /// weighted motion of their clips over the last frame they were advanced.
+    pub fn root_motion(
+        &mut self,
+        name: &str,
+        dt: f32,
+        root: Option<&RootMotion>,
+        clip_assets: &Assets<AnimationClip>,
+    ) -> Option<(Vec3, f32)> {
+        let root = root?;
+        let idx = *self.anim.get(name)?;
+        if let Some(space) = self.anim.blend_spaces.get(&idx) {
+            let state = self.params.blend.get(&idx).copied().unwrap_or_default();
+            let weights = space.weights(state.position);
+            return space.clips.iter().zip(weights).try_fold(
+                (Vec3::ZERO, 0.0),
+                |(translation, yaw), ((clip, _, duration), weight)| {
+                    let (clip_translation, clip_yaw) = root.delta(
+                        clip_assets,
+                        *clip,
+                        state.prev_phase * duration,
+                        state.phase * duration,
+                    )?;
+                    Some((
+                        translation + clip_translation * weight,
+                        yaw + clip_yaw * weight,
+                    ))
+                },
+            );
+        }
+        let anim = self.player.animation(idx)?;
+        let to = anim.seek_time();
+        root.delta(clip_assets, idx, to - dt * anim.speed(), to)
+    }
+
+    /// Seconds from the start of a clip to one of its notifies
+    pub fn notify_time(&self, name: &str, notify: &str) -> Option<f32> {
+        let idx = self.anim.get(name)?;
+        self.anim
+            .notifies
+            .get(idx)?
+            .notifies
+            .iter()
+            .find(|n| n.name == notify)
+            .map(|n| n.time)
     }
 
     pub fn playing_idx(&mut self, idx: AnimationNodeIndex) -> bool {
         self.player.is_playing_animation(idx)
     }
 
-    pub fn animation(&mut self, name: &str) -> std::option::Option<ActiveAnimation> {
-        self.player
-            .animation(
-                *self
-                    .anim
-                    .get(name)
-                    .unwrap_or_else(|| panic!("{}", self.no_name_msg(name))),
-            )
-            .copied()
+    pub fn animation(
+        &mut self,
+        clip: impl AsRef<str>,
+    ) -> Result<Option<ActiveAnimation>, AnimError> {
+        Ok(self.player.animation(self.anim.node(clip)?).copied())
     }
 
     pub fn animation_idx(
@@ -122,9 +1322,659 @@ impl<'a> AnimPlayerController<'a> {
     }
 }
 
-pub fn ramp_up_down_anim(seek_f: f32, move_start: f32, move_length: f32, pow_curve: f32) -> f32 {
-    let mut mspeed = (seek_f - move_start) / move_length;
-    mspeed = 1.0 - (mspeed * 2.0 - 1.0).abs();
-    mspeed = mspeed.powf(pow_curve);
-    mspeed
+/// Keeps the clips of each playing blend space in step and weighted by its parameter. The blend node's own weight
+/// comes from [`AnimationTransitions`] and is shared out between the clips.
+fn update_blend_spaces(
+    mut players: Query<(&mut AnimationPlayer, &AnimationIndices, &mut AnimParams)>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (mut player, indices, mut params) in &mut players {
+        for (idx, space) in &indices.blend_spaces {
+            let Some(node) = player.animation(*idx).copied() else {
+                for (clip, _, _) in &space.clips {
+                    player.stop(*clip);
+                }
+                continue;
+            };
+            let state = params.blend.entry(*idx).or_default();
+            let weights = space.weights(state.position);
+            let duration: f32 = space
+                .clips
+                .iter()
+                .zip(&weights)
+                .map(|((_, _, duration), weight)| duration * weight)
+                .sum();
+            state.prev_phase = state.phase;
+            if !node.is_paused() {
+                state.phase += dt * node.speed() / duration.max(f32::EPSILON);
+                state.phase = state.phase.rem_euclid(1.0);
+            }
+            for ((clip, _, clip_duration), weight) in space.clips.iter().zip(weights) {
+                // Bevy doesn't advance them, the phase does
+                let anim = player.play(*clip);
+                anim.set_speed(0.0)
+                    .repeat()
+                    .seek_to(state.phase * clip_duration);
+                anim.set_weight(node.weight() * weight);
+            }
+        }
+    }
+}
+
+/// Stops layers that played out and keeps their weights in line with [`AnimParams`]
+fn update_layers(mut players: Query<(&mut AnimationPlayer, &AnimationIndices, &AnimParams)>) {
+    for (mut player, indices, params) in &mut players {
+        for (idx, layer) in &indices.layers {
+            let Some(anim) = player.animation_mut(*idx) else {
+                continue;
+            };
+            if anim.is_finished() {
+                player.stop(*idx);
+                continue;
+            }
+            let weight = params.layers.get(idx).copied().unwrap_or(1.0);
+            anim.set_weight(layer.blend.active_weight(weight));
+        }
+    }
+}
+
+/// Adds each additive layer on top of the pose bevy blended, as the difference from the layer's first frame
+fn apply_additive_layers(
+    players: Query<(
+        &AnimationPlayer,
+        &AnimationIndices,
+        &AnimParams,
+        &AdditiveLayerBones,
+        Option<&AnimLod>,
+    )>,
+    mut bones: Query<&mut Transform>,
+    clip_assets: Res<Assets<AnimationClip>>,
+) {
+    for (player, indices, params, layer_bones, lod) in &players {
+        // Offsets are added to the sampled pose, so they'd pile up on a kept one
+        if !AnimLod::sampled(lod) {
+            continue;
+        }
+        for (idx, layer_bones) in &layer_bones.0 {
+            let Some(layer) = indices.layers.get(idx) else {
+                continue;
+            };
+            let Some(anim) = player.animation(*idx) else {
+                continue;
+            };
+            let Some(clip) = clip_assets.get(&layer.clip) else {
+                continue;
+            };
+            let weight = params.layers.get(idx).copied().unwrap_or(1.0);
+            let t = anim.seek_time();
+            for (bone, target) in layer_bones {
+                let (Ok(mut trans), Some(curves)) =
+                    (bones.get_mut(*bone), clip.curves_for_target(*target))
+                else {
+                    continue;
+                };
+                for curve in curves {
+                    let start = curve.keyframe_timestamps.first().copied().unwrap_or(0.0);
+                    match &curve.keyframes {
+                        Keyframes::Translation(values) => {
+                            let reference = sample_curve(curve, values, start, Vec3::lerp);
+                            let current = sample_curve(curve, values, t, Vec3::lerp);
+                            trans.translation += (current - reference) * weight;
+                        }
+                        Keyframes::Rotation(values) => {
+                            let reference = sample_curve(curve, values, start, Quat::slerp);
+                            let current = sample_curve(curve, values, t, Quat::slerp);
+                            let delta = reference.inverse() * current;
+                            trans.rotation *= Quat::IDENTITY.slerp(delta, weight);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn emit_anim_notifies(
+    mut players: Query<(
+        Entity,
+        &AnimationPlayer,
+        &AnimationIndices,
+        &mut AnimNotifyCursor,
+    )>,
+    mut notify_events: EventWriter<AnimNotifyEvent>,
+) {
+    for (entity, player, indices, mut cursor) in &mut players {
+        if indices.notifies.is_empty() {
+            continue;
+        }
+        cursor.0.retain(|idx, _| player.is_playing_animation(*idx));
+        for (idx, anim) in player.playing_animations() {
+            let seek = anim.seek_time();
+            let completions = anim.completions();
+            let last = cursor.0.insert(*idx, (seek, completions));
+            let Some(clip) = indices.notifies.get(idx) else {
+                continue;
+            };
+            let mut send = |from: f32, to: f32| {
+                for notify in clip.between(from, to) {
+                    notify_events.send(AnimNotifyEvent {
+                        entity,
+                        clip: *idx,
+                        name: notify.name.clone(),
+                    });
+                }
+            };
+            match last {
+                // Just started, or restarted from the beginning
+                None => send(-1.0, seek),
+                Some((last_seek, last_completions))
+                    if completions < last_completions
+                        || (completions == last_completions && seek < last_seek) =>
+                {
+                    send(-1.0, seek)
+                }
+                Some((last_seek, last_completions)) if completions > last_completions => {
+                    send(last_seek, clip.duration);
+                    // A finished clip stays at its end
+                    if !anim.is_finished() {
+                        send(-1.0, seek);
+                    }
+                }
+                Some((last_seek, _)) => send(last_seek, seek),
+            }
+        }
+    }
+}
+
+/// Possible errors that can be produced by [`AnimNotifiesLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AnimNotifiesLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON Error](ron::error::SpannedError)
+    #[error("Could not parse RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+/// Asset loader for `.notifies.ron` files.
+#[derive(Default)]
+pub struct AnimNotifiesLoader;
+
+impl AssetLoader for AnimNotifiesLoader {
+    type Asset = AnimNotifies;
+    type Settings = ();
+    type Error = AnimNotifiesLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes::<AnimNotifies>(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["notifies.ron"]
+    }
+}
+
+/// Declarative clip selection, loaded from `.anim.ron` files. Gameplay code only sets the [`AnimStateMachinePlayer`]
+/// parameters, [`run_anim_state_machines`] picks the clips.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct AnimStateMachine {
+    pub initial: String,
+    pub states: bevy::utils::HashMap<String, AnimStateDef>,
+    /// Checked from every state before the state's own transitions
+    #[serde(default)]
+    pub any_state: Vec<AnimTransitionDef>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimStateDef {
+    pub clip: String,
+    pub speed: f32,
+    /// Parameter the speed is multiplied by, 1 if it isn't set
+    #[serde(default)]
+    pub speed_param: Option<String>,
+    /// Parameter that moves the blend space, for states whose clip is a 1D blend space
+    #[serde(default)]
+    pub blend_param: Option<String>,
+    #[serde(default)]
+    pub looping: bool,
+    /// First one whose conditions all hold is taken
+    #[serde(default)]
+    pub transitions: Vec<AnimTransitionDef>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimTransitionDef {
+    pub to: String,
+    #[serde(default)]
+    pub when: Vec<AnimCondition>,
+    /// Seconds to blend into the new clip
+    #[serde(default = "default_blend")]
+    pub blend: f32,
+}
+
+fn default_blend() -> f32 {
+    0.1
+}
+
+impl AnimStateMachine {
+    /// Every parameter the machine reads, with repeats
+    pub fn params(&self) -> impl Iterator<Item = &str> {
+        let transitions = self
+            .states
+            .values()
+            .flat_map(|state| state.transitions.iter())
+            .chain(self.any_state.iter());
+        let conditions = transitions
+            .flat_map(|transition| transition.when.iter())
+            .filter_map(|condition| match condition {
+                AnimCondition::Is(param)
+                | AnimCondition::Not(param)
+                | AnimCondition::Above(param, _)
+                | AnimCondition::Below(param, _) => Some(param.as_str()),
+                AnimCondition::Finished => None,
+            });
+        let state_params = self.states.values().flat_map(|state| {
+            [&state.speed_param, &state.blend_param]
+                .into_iter()
+                .flatten()
+                .map(|param| param.as_str())
+        });
+        conditions.chain(state_params)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum AnimCondition {
+    /// Parameter is set and non-zero
+    Is(String),
+    Not(String),
+    Above(String, f32),
+    Below(String, f32),
+    /// Clip of the current state played to the end, for states that don't loop
+    Finished,
+}
+
+/// Runs an [`AnimStateMachine`] on the entity with the [`AnimationPlayer`]
+#[derive(Component, Clone, Debug)]
+pub struct AnimStateMachinePlayer {
+    pub machine: Handle<AnimStateMachine>,
+    params: HashMap<String, f32>,
+    /// None until the machine is first evaluated
+    state: Option<String>,
+    clip: String,
+    /// State was entered on the last evaluation
+    changed: bool,
+}
+
+impl AnimStateMachinePlayer {
+    pub fn new(machine: Handle<AnimStateMachine>) -> Self {
+        AnimStateMachinePlayer {
+            machine,
+            params: HashMap::new(),
+            state: None,
+            clip: String::new(),
+            changed: false,
+        }
+    }
+
+    pub fn set(&mut self, param: impl AsRef<str>, value: f32) {
+        let param = param.as_ref();
+        match self.params.get_mut(param) {
+            Some(current) => *current = value,
+            None => {
+                self.params.insert(param.to_string(), value);
+            }
+        }
+    }
+
+    pub fn set_bool(&mut self, param: impl AsRef<str>, value: bool) {
+        self.set(param, if value { 1.0 } else { 0.0 });
+    }
+
+    pub fn param(&self, param: impl AsRef<str>) -> f32 {
+        self.params.get(param.as_ref()).copied().unwrap_or(0.0)
+    }
+
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    pub fn in_state(&self, state: impl AsRef<str>) -> bool {
+        self.state() == Some(state.as_ref())
+    }
+
+    /// Clip of the current state
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    /// True if `state` was entered on the last evaluation
+    pub fn entered(&self, state: impl AsRef<str>) -> bool {
+        self.changed && self.in_state(state)
+    }
+
+    fn speed(&self, def: &AnimStateDef) -> f32 {
+        let mult = def
+            .speed_param
+            .as_ref()
+            .and_then(|param| self.params.get(param))
+            .copied()
+            .unwrap_or(1.0);
+        def.speed * mult
+    }
+
+    fn holds(&self, condition: &AnimCondition, player: &mut AnimPlayerController) -> bool {
+        // Missing clips count as finished so the machine can move on
+        match condition {
+            AnimCondition::Is(param) => self.param(param) != 0.0,
+            AnimCondition::Not(param) => self.param(param) == 0.0,
+            AnimCondition::Above(param, value) => self.param(param) > *value,
+            AnimCondition::Below(param, value) => self.param(param) < *value,
+            AnimCondition::Finished => match player.animation(&self.clip) {
+                Ok(Some(anim)) => anim.is_finished(),
+                Ok(None) | Err(_) => true,
+            },
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        machine: &AnimStateMachine,
+        player: &mut AnimPlayerController,
+    ) -> Result<(), AnimError> {
+        self.changed = false;
+        let current = self
+            .state
+            .as_ref()
+            .and_then(|state| machine.states.get(state));
+        let next = match current {
+            None => Some((&machine.initial, default_blend())),
+            // Transitions into the current state are skipped, so they can be shared between states
+            Some(def) => machine
+                .any_state
+                .iter()
+                .chain(def.transitions.iter())
+                .filter(|transition| Some(&transition.to) != self.state.as_ref())
+                .find(|transition| {
+                    transition
+                        .when
+                        .iter()
+                        .all(|condition| self.holds(condition, player))
+                })
+                .map(|transition| (&transition.to, transition.blend)),
+        };
+        if let Some((next, blend)) = next {
+            let Some(def) = machine.states.get(next) else {
+                warn_once!("Animation state machine has no state named {}", next);
+                return Ok(());
+            };
+            // Same clip carries on from its current pose, so a paused walk can stand in for idle
+            if def.clip != self.clip || !player.playing(&def.clip)? {
+                player.play(&def.clip, blend, self.speed(def), def.looping)?;
+            }
+            self.state = Some(next.clone());
+            self.clip = def.clip.clone();
+            self.changed = true;
+        }
+        // Speed parameters can change at any time
+        if let Some(def) = self
+            .state
+            .as_ref()
+            .and_then(|state| machine.states.get(state))
+        {
+            player.set_speed(&def.clip, self.speed(def))?;
+            if let Some(param) = &def.blend_param {
+                player.set_blend_1d(&def.clip, self.param(param))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates every [`AnimStateMachinePlayer`] before the clips are advanced
+pub fn run_anim_state_machines(
+    mut players: Query<(
+        &mut AnimationTransitions,
+        &mut AnimationPlayer,
+        &AnimationIndices,
+        &mut AnimParams,
+        &mut AnimStateMachinePlayer,
+    )>,
+    machines: Res<Assets<AnimStateMachine>>,
+) {
+    for (mut transitions, mut player, indices, mut params, mut state_machine) in &mut players {
+        let Some(machine) = machines.get(&state_machine.machine) else {
+            continue;
+        };
+        let mut player =
+            AnimPlayerController::new(&mut transitions, &mut player, indices, &mut params);
+        if let Err(err) = state_machine.update(machine, &mut player) {
+            warn_once!("Animation state machine: {}", err);
+        }
+    }
+}
+
+/// Possible errors that can be produced by [`AnimStateMachineLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AnimStateMachineLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON Error](ron::error::SpannedError)
+    #[error("Could not parse RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+/// Asset loader for `.anim.ron` files.
+#[derive(Default)]
+pub struct AnimStateMachineLoader;
+
+impl AssetLoader for AnimStateMachineLoader {
+    type Asset = AnimStateMachine;
+    type Settings = ();
+    type Error = AnimStateMachineLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes::<AnimStateMachine>(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        animation::{AnimationPlugin, VariableCurve},
+        time::TimeUpdateStrategy,
+    };
+
+    use super::*;
+
+    crate::anim_names! {
+        enum TestClip {
+            Walk = "Walk",
+        }
+    }
+
+    struct TestAnim;
+
+    impl AnimClips for TestAnim {
+        type Clip = TestClip;
+        type Blend = NoAnimNames;
+        type Layer = NoAnimNames;
+        type State = NoAnimNames;
+        type Param = NoAnimNames;
+
+        fn get_gltf_id(_mesh_assets: &MeshAssets) -> Handle<Gltf> {
+            Handle::default()
+        }
+    }
+
+    const DT: f32 = 0.1;
+
+    /// One bone turning a quarter turn over a 1s `Walk` clip, with a `step` notify halfway. The camera is past
+    /// the freeze distance so every pose after the first is skipped.
+    fn lod_app() -> (App, Entity, Entity, AnimationNodeIndex) {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            AnimationPlugin,
+            AnimNotifyPlugin,
+            AnimBlendPlugin,
+            RootMotionPlugin,
+            AnimLodPlugin,
+        ))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            DT,
+        )))
+        .insert_resource(AnimLodSettings {
+            freeze_distance: 50.0,
+            ..default()
+        });
+
+        let bone_name = Name::new("Bone");
+        let target = AnimationTargetId::from_name(&bone_name);
+        let mut clip = AnimationClip::default();
+        clip.add_curve_to_target(
+            target,
+            VariableCurve {
+                keyframe_timestamps: vec![0.0, 1.0],
+                keyframes: Keyframes::Rotation(vec![
+                    Quat::IDENTITY,
+                    Quat::from_rotation_y(PI * 0.5),
+                ]),
+                interpolation: Interpolation::Linear,
+            },
+        );
+        let world = app.world_mut();
+        let clip = world.resource_mut::<Assets<AnimationClip>>().add(clip);
+        let named_animations: bevy::utils::HashMap<Box<str>, Handle<AnimationClip>> =
+            [("Walk".into(), clip)].into_iter().collect();
+        let notifies = AnimNotifies(
+            [(
+                "Walk".to_string(),
+                vec![AnimNotify {
+                    name: "step".to_string(),
+                    time: 0.5,
+                    at_end: false,
+                }],
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let shared = world.resource_scope(|world, mut graphs: Mut<Assets<AnimationGraph>>| {
+            SharedAnimGraph::build::<TestAnim>(
+                &named_animations,
+                Some(&notifies),
+                |_| Vec::new(),
+                &mut graphs,
+                &mut world.resource_mut::<Assets<AnimationClip>>(),
+            )
+        });
+        let walk = shared.indices.node(TestClip::Walk).unwrap();
+
+        let mut player = AnimationPlayer::default();
+        player.start(walk).seek_to(0.25);
+        let player = world
+            .spawn((
+                player,
+                shared.graph,
+                shared.indices,
+                AnimNotifyCursor::default(),
+                AnimParams::default(),
+                AnimLod::default(),
+                GlobalTransform::IDENTITY,
+            ))
+            .id();
+        let bone = world
+            .spawn((
+                bone_name,
+                AnimationTarget { id: target, player },
+                Transform::IDENTITY,
+            ))
+            .set_parent(player)
+            .id();
+        world.spawn((
+            RenderPlayer {
+                logical_entity: player,
+            },
+            GlobalTransform::from_xyz(0.0, 0.0, 100.0),
+            Frustum::default(),
+        ));
+        (app, player, bone, walk)
+    }
+
+    fn steps(app: &mut App) -> usize {
+        app.world_mut()
+            .resource_mut::<Events<AnimNotifyEvent>>()
+            .drain()
+            .filter(|event| event.name == "step")
+            .count()
+    }
+
+    #[test]
+    fn skipped_poses_keep_bones_and_advance_clips() {
+        let (mut app, player, bone, walk) = lod_app();
+        app.update();
+        let pose = *app.world().get::<Transform>(bone).unwrap();
+        assert_ne!(pose, Transform::IDENTITY, "first pose is sampled");
+        let mut step_count = steps(&mut app);
+
+        // 0.25s in, the clip ends after 8 more frames
+        for _ in 0..8 {
+            app.update();
+            step_count += steps(&mut app);
+            assert_eq!(*app.world().get::<Transform>(bone).unwrap(), pose);
+            let anim = app
+                .world()
+                .get::<AnimationPlayer>(player)
+                .unwrap()
+                .animation(walk)
+                .unwrap();
+            assert_eq!(anim.weight(), 1.0, "weight is restored after sampling");
+        }
+        let anim = *app
+            .world()
+            .get::<AnimationPlayer>(player)
+            .unwrap()
+            .animation(walk)
+            .unwrap();
+        assert!(anim.seek_time() > 0.95);
+        assert!(anim.is_finished());
+        assert_eq!(step_count, 1);
+
+        // Close enough to sample every frame again, the pose catches up with the clip
+        let camera = app
+            .world_mut()
+            .query_filtered::<Entity, With<RenderPlayer>>()
+            .single(app.world());
+        app.world_mut().despawn(camera);
+        app.update();
+        let caught_up = *app.world().get::<Transform>(bone).unwrap();
+        assert!(caught_up
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_y(PI * 0.5), 1e-4));
+    }
 }
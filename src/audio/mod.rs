@@ -10,8 +10,10 @@ pub mod spatial;
 pub struct AudioAssets {
     #[asset(path = "audio/theme3.flac")]
     pub game_music: Handle<KiraSoundData>,
-    #[asset(path = "audio/gun.flac")]
-    pub gun: Handle<KiraSoundData>,
+    #[asset(path = "audio/reload.flac")]
+    pub reload: Handle<KiraSoundData>,
+    #[asset(path = "audio/footstep.flac")]
+    pub footstep: Handle<KiraSoundData>,
 }
 
 pub struct GameAudioPlugin;
@@ -19,6 +19,7 @@ use std::f32::consts::TAU;
 
 use crate::audio::spatial::GameAudioReceiver;
 use crate::fps_controller;
+use crate::physics::PLAYER_GROUP;
 
 pub struct CharacterController;
 impl Plugin for CharacterController {
@@ -54,6 +55,7 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
                 combine_rule: CoefficientCombineRule::Min,
             },
             ActiveEvents::COLLISION_EVENTS,
+            CollisionGroups::new(PLAYER_GROUP, Group::ALL),
             Velocity::zero(),
             RigidBody::Dynamic,
             Sleeping::disabled(),
@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+
+use crate::{
+    character_controller::Player, difficulty::Difficulty, menu::menu_ui,
+    units::hit_volumes::HitZone, GameLoading,
+};
+
+pub struct DamagePlugin;
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>().add_systems(
+            Update,
+            process_damage
+                .run_if(in_state(GameLoading::Loaded))
+                .before(menu_ui),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DamageKind {
+    Bullet,
+    Explosion,
+    Melee,
+    /// Enemy projectiles
+    Spit,
+}
+
+/// All damage goes through this event so resistances, hit zones and kill credit are handled in one place.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub target: Entity,
+    /// Before resistances and hit zone multipliers
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub hit_point: Vec3,
+    pub zone: Option<HitZone>,
+    /// Who caused the damage, the player gets kill credit if this is the player entity
+    pub source: Option<Entity>,
+}
+
+/// Health of things that aren't the player
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Damageable {
+    pub health: f32,
+    /// Damage multiplier per kind, kinds not listed take full damage
+    pub resistances: &'static [(DamageKind, f32)],
+    /// Damage multiplier per hit zone, zones not listed take full damage
+    pub zone_multipliers: &'static [(HitZone, f32)],
+}
+
+/// Killing blow or most recent hit on a [`Damageable`], used to knock corpses over
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct LastHit {
+    pub point: Vec3,
+    /// Away from the source of the damage
+    pub direction: Vec3,
+    /// After resistances and hit zone multipliers
+    pub amount: f32,
+}
+
+impl Damageable {
+    pub fn dead(&self) -> bool {
+        self.health < 0.0
+    }
+
+    pub fn scaled_damage(&self, event: &DamageEvent) -> f32 {
+        let resistance = self
+            .resistances
+            .iter()
+            .find(|(kind, _)| *kind == event.kind)
+            .map_or(1.0, |(_, mult)| *mult);
+        let zone = event
+            .zone
+            .and_then(|zone| self.zone_multipliers.iter().find(|(z, _)| *z == zone))
+            .map_or(1.0, |(_, mult)| *mult);
+        event.amount * resistance * zone
+    }
+}
+
+pub fn process_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut damageables: Query<(&mut Damageable, &GlobalTransform, Option<&mut LastHit>)>,
+    mut players: Query<&mut Player>,
+    sources: Query<&GlobalTransform>,
+    difficulty: Res<Difficulty>,
+) {
+    for event in damage_events.read() {
+        if let Ok((mut damageable, target_trans, last_hit)) = damageables.get_mut(event.target) {
+            let was_alive = !damageable.dead();
+            let amount = damageable.scaled_damage(event);
+            damageable.health -= amount;
+            if let Some(mut last_hit) = last_hit {
+                let from = event
+                    .source
+                    .and_then(|source| sources.get(source).ok())
+                    .map_or(target_trans.translation(), |source| source.translation());
+                *last_hit = LastHit {
+                    point: event.hit_point,
+                    direction: (event.hit_point - from).normalize_or_zero(),
+                    amount,
+                };
+            }
+            if was_alive && damageable.dead() {
+                if let Some(mut player) =
+                    event.source.and_then(|source| players.get_mut(source).ok())
+                {
+                    player.kills += 1;
+                }
+            }
+        } else if let Ok(mut player) = players.get_mut(event.target) {
+            player.health -= event.amount * difficulty.damage;
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use bevy::{
+    core::FrameCount,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use crate::{
+    hash_noise,
+    menu::UserSettings,
+    util::{EntityRing, RingSlot},
+    GameLoading,
+};
+
+/// Seconds before a decal starts to fade
+const DECAL_LIFETIME: f32 = 30.0;
+/// Seconds the decal takes to shrink away once it's past its lifetime
+const DECAL_FADE_TIME: f32 = 2.0;
+/// Distance from the surface, to avoid z-fighting
+const DECAL_SURFACE_OFFSET: f32 = 0.01;
+const DECAL_TEXTURE_SIZE: u32 = 32;
+
+pub struct DecalPlugin;
+impl Plugin for DecalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DecalEvent>()
+            .init_resource::<ImpactDecals>()
+            .add_systems(
+                Update,
+                (spawn_decals, age_decals)
+                    .chain()
+                    .run_if(in_state(GameLoading::Loaded)),
+            );
+    }
+}
+
+/// Place an impact decal on a surface
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DecalEvent {
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// Width in meters
+    pub size: f32,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct ImpactDecal {
+    spawned: f32,
+    size: f32,
+}
+
+/// Ring buffer of decals. Once `UserSettings::max_decals` are placed the oldest one is moved to the new impact.
+#[derive(Resource)]
+pub struct ImpactDecals {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    decals: EntityRing,
+}
+
+impl FromWorld for ImpactDecals {
+    fn from_world(world: &mut World) -> Self {
+        // Soft dark spot, so there's no need for a texture asset
+        let mut data = Vec::with_capacity((DECAL_TEXTURE_SIZE * DECAL_TEXTURE_SIZE * 4) as usize);
+        for y in 0..DECAL_TEXTURE_SIZE {
+            for x in 0..DECAL_TEXTURE_SIZE {
+                let uv = (Vec2::new(x as f32, y as f32) + 0.5) / DECAL_TEXTURE_SIZE as f32;
+                let d = uv.distance(Vec2::splat(0.5)) * 2.0;
+                let alpha = (1.0 - d).clamp(0.0, 1.0).powf(0.5);
+                data.extend_from_slice(&[10, 8, 6, (alpha * 230.0) as u8]);
+            }
+        }
+        let image = Image::new(
+            Extent3d {
+                width: DECAL_TEXTURE_SIZE,
+                height: DECAL_TEXTURE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        let image = world.resource_mut::<Assets<Image>>().add(image);
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Plane3d::default().mesh().size(1.0, 1.0));
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color_texture: Some(image),
+                alpha_mode: AlphaMode::Blend,
+                perceptual_roughness: 1.0,
+                depth_bias: 1.0,
+                ..default()
+            });
+        Self {
+            mesh,
+            material,
+            decals: EntityRing::default(),
+        }
+    }
+}
+
+impl ImpactDecals {
+    /// Despawns every decal, for restarting the level
+    pub fn despawn_all(&mut self, commands: &mut Commands) {
+        self.decals.despawn_all(commands);
+    }
+}
+
+fn spawn_decals(
+    mut commands: Commands,
+    mut decal_events: EventReader<DecalEvent>,
+    mut impact_decals: ResMut<ImpactDecals>,
+    settings: Res<UserSettings>,
+    time: Res<Time>,
+    frame: Res<FrameCount>,
+) {
+    let impact_decals = &mut *impact_decals;
+    let cap = settings.max_decals;
+    impact_decals.decals.trim(&mut commands, cap);
+    for (i, event) in decal_events.read().enumerate() {
+        if cap == 0 {
+            continue;
+        }
+        let yaw = hash_noise(frame.0, i as u32, 7) * std::f32::consts::TAU;
+        let transform =
+            Transform::from_translation(event.point + event.normal * DECAL_SURFACE_OFFSET)
+                .with_rotation(
+                    Quat::from_rotation_arc(Vec3::Y, event.normal) * Quat::from_rotation_y(yaw),
+                )
+                .with_scale(Vec3::splat(event.size));
+        let decal = ImpactDecal {
+            spawned: time.elapsed_seconds(),
+            size: event.size,
+        };
+        let bundle = (
+            PbrBundle {
+                mesh: impact_decals.mesh.clone(),
+                material: impact_decals.material.clone(),
+                transform,
+                ..default()
+            },
+            decal,
+        );
+        match impact_decals.decals.next(&mut commands, cap) {
+            Some(RingSlot::Reuse(mut ecmds, _)) => {
+                ecmds.insert(bundle);
+            }
+            Some(RingSlot::Spawn) => {
+                let entity = commands.spawn(bundle).id();
+                impact_decals.decals.push(entity, ());
+            }
+            None => (),
+        }
+    }
+}
+
+fn age_decals(mut decals: Query<(&mut Transform, &mut Visibility, &ImpactDecal)>, time: Res<Time>) {
+    let t = time.elapsed_seconds();
+    for (mut trans, mut vis, decal) in &mut decals {
+        let fade = ((t - decal.spawned - DECAL_LIFETIME) / DECAL_FADE_TIME).clamp(0.0, 1.0);
+        if fade >= 1.0 {
+            *vis = Visibility::Hidden;
+        } else if fade > 0.0 {
+            trans.scale = Vec3::splat(decal.size * (1.0 - fade));
+        }
+    }
+}
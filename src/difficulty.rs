@@ -0,0 +1,263 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    character_controller::Player,
+    menu::menu_ui,
+    waves::{WaveAssets, WaveScript},
+    GameLoading,
+};
+
+/// Performance score above which the tier goes up, and below the negative of which it goes down
+const TIER_CHANGE_SCORE: f32 = 0.5;
+/// Accuracy is treated as on target until at least this many shots are in the window
+const MIN_SHOTS_FOR_ACCURACY: usize = 10;
+
+pub struct DifficultyPlugin;
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ShotEvent>()
+            .init_resource::<Difficulty>()
+            .add_systems(
+                Update,
+                update_difficulty
+                    .run_if(in_state(GameLoading::Loaded))
+                    .before(menu_ui),
+            );
+    }
+}
+
+/// Sent for each shot the player fires, once it's known whether it hit a unit
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ShotEvent {
+    pub hit: bool,
+}
+
+/// Multipliers applied at one difficulty tier
+#[derive(Clone, Debug, Deserialize)]
+pub struct DifficultyTier {
+    pub name: String,
+    /// Wave spawn intervals are divided by this
+    pub spawn_rate: f32,
+    /// Unit walk and turn animation speed, movement follows the animation
+    pub speed: f32,
+    /// Damage the player takes
+    pub damage: f32,
+}
+
+/// Part of the [`WaveScript`]. The player's performance over the last `window` seconds is compared to the targets
+/// every `eval_interval` seconds, moving up or down one tier at a time.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DifficultyDef {
+    /// Seconds of history the performance is measured over
+    pub window: f32,
+    pub eval_interval: f32,
+    /// Easiest first
+    pub tiers: Vec<DifficultyTier>,
+    pub start_tier: usize,
+    pub target_kills_per_minute: f32,
+    pub target_damage_per_minute: f32,
+    /// Fraction of shots that hit a unit
+    pub target_accuracy: f32,
+}
+
+/// Timestamped samples, trimmed to the last `window` seconds each frame
+#[derive(Default)]
+struct SlidingWindow(VecDeque<(f32, f32)>);
+
+impl SlidingWindow {
+    fn push(&mut self, t: f32, value: f32) {
+        self.0.push_back((t, value));
+    }
+
+    fn trim(&mut self, t: f32, window: f32) {
+        while self
+            .0
+            .front()
+            .is_some_and(|(sample_t, _)| *sample_t < t - window)
+        {
+            self.0.pop_front();
+        }
+    }
+
+    fn sum(&self) -> f32 {
+        self.0.iter().map(|(_, value)| value).sum()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Current difficulty tier, adjusted from the player's recent performance. Reset when the player restarts.
+#[derive(Resource)]
+pub struct Difficulty {
+    pub tier: usize,
+    pub tier_name: String,
+    pub spawn_rate: f32,
+    pub speed: f32,
+    pub damage: f32,
+    started: bool,
+    /// Seconds of history measured so far, shorter than the window at the start
+    measured: f32,
+    started_at: f32,
+    last_eval: f32,
+    last_health: f32,
+    last_kills: u32,
+    damage_taken: SlidingWindow,
+    kills: SlidingWindow,
+    /// 1 for shots that hit, 0 for misses
+    shots: SlidingWindow,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self {
+            tier: 0,
+            tier_name: String::new(),
+            spawn_rate: 1.0,
+            speed: 1.0,
+            damage: 1.0,
+            started: false,
+            measured: 0.0,
+            started_at: 0.0,
+            last_eval: 0.0,
+            last_health: 0.0,
+            last_kills: 0,
+            damage_taken: default(),
+            kills: default(),
+            shots: default(),
+        }
+    }
+}
+
+impl Difficulty {
+    fn set_tier(&mut self, def: &DifficultyDef, tier: usize) {
+        let Some(last) = def.tiers.len().checked_sub(1) else {
+            return;
+        };
+        self.tier = tier.min(last);
+        let tier = &def.tiers[self.tier];
+        self.tier_name = tier.name.clone();
+        self.spawn_rate = tier.spawn_rate;
+        self.speed = tier.speed;
+        self.damage = tier.damage;
+    }
+
+    pub fn kills_per_minute(&self) -> f32 {
+        self.kills.sum() * 60.0 / self.measured.max(1.0)
+    }
+
+    pub fn damage_per_minute(&self) -> f32 {
+        self.damage_taken.sum() * 60.0 / self.measured.max(1.0)
+    }
+
+    /// None until there are enough shots in the window
+    pub fn accuracy(&self) -> Option<f32> {
+        (self.shots.len() >= MIN_SHOTS_FOR_ACCURACY)
+            .then(|| self.shots.sum() / self.shots.len() as f32)
+    }
+
+    /// 0 when the player performs exactly at the targets, positive when doing better
+    pub fn performance_score(&self, def: &DifficultyDef) -> f32 {
+        let kills = self.kills_per_minute() / def.target_kills_per_minute.max(0.001);
+        let accuracy = self
+            .accuracy()
+            .map_or(1.0, |accuracy| accuracy / def.target_accuracy.max(0.001));
+        let damage = self.damage_per_minute() / def.target_damage_per_minute.max(0.001);
+        (kills + accuracy - damage - 1.0) / 3.0
+    }
+}
+
+fn update_difficulty(
+    mut difficulty: ResMut<Difficulty>,
+    mut shot_events: EventReader<ShotEvent>,
+    player: Query<&Player, With<Camera3d>>,
+    wave_assets: Res<WaveAssets>,
+    scripts: Res<Assets<WaveScript>>,
+    time: Res<Time>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let Some(def) = scripts
+        .get(&wave_assets.script)
+        .map(|script| &script.difficulty)
+    else {
+        return;
+    };
+    let t = time.elapsed_seconds();
+
+    // Player stats are reset on restart
+    if player.activity_start_time.is_none() {
+        if difficulty.started {
+            *difficulty = Difficulty::default();
+        }
+        shot_events.clear();
+        return;
+    }
+    if !difficulty.started {
+        difficulty.started = true;
+        difficulty.set_tier(def, def.start_tier);
+        difficulty.started_at = t;
+        difficulty.last_eval = t;
+        difficulty.last_health = player.health;
+        difficulty.last_kills = player.kills;
+        info!("Difficulty starts at {}", difficulty.tier_name);
+    }
+
+    let damage_taken = difficulty.last_health - player.health;
+    if damage_taken > 0.0 {
+        difficulty.damage_taken.push(t, damage_taken);
+    }
+    let kills = player.kills.saturating_sub(difficulty.last_kills);
+    if kills > 0 {
+        difficulty.kills.push(t, kills as f32);
+    }
+    difficulty.last_health = player.health;
+    difficulty.last_kills = player.kills;
+    for shot in shot_events.read() {
+        difficulty.shots.push(t, if shot.hit { 1.0 } else { 0.0 });
+    }
+
+    let difficulty = &mut *difficulty;
+    difficulty.measured = (t - difficulty.started_at).min(def.window);
+    for window in [
+        &mut difficulty.damage_taken,
+        &mut difficulty.kills,
+        &mut difficulty.shots,
+    ] {
+        window.trim(t, def.window);
+    }
+
+    if player.health < 0.0 || t < difficulty.last_eval + def.eval_interval {
+        return;
+    }
+    difficulty.last_eval = t;
+    let score = difficulty.performance_score(def);
+    let tier = if score > TIER_CHANGE_SCORE {
+        difficulty.tier + 1
+    } else if score < -TIER_CHANGE_SCORE {
+        difficulty.tier.saturating_sub(1)
+    } else {
+        difficulty.tier
+    };
+    let old_name = difficulty.tier_name.clone();
+    difficulty.set_tier(def, tier);
+    info!(
+        "Difficulty {} -> {}: score {:.2}, kills/min {:.1}, damage/min {:.1}, accuracy {}",
+        old_name,
+        difficulty.tier_name,
+        score,
+        difficulty.kills_per_minute(),
+        difficulty.damage_per_minute(),
+        difficulty
+            .accuracy()
+            .map_or("-".to_string(), |accuracy| format!(
+                "{:.0}%",
+                accuracy * 100.0
+            )),
+    );
+}
@@ -1,11 +1,13 @@
 // https://github.com/qhdwight/bevy_fps_controller/blob/6cb13a3063b95e2a16a8838cb9c4d328034e3901/src/controller.rs
-// Vendored to just add .exclude_sensors() to the QueryFilter below
+// Vendored to just add .exclude_sensors() and .groups(SKIP_CORPSES) to the QueryFilters below
 
 use std::f32::consts::*;
 
 use bevy::{input::mouse::MouseMotion, math::Vec3Swizzles, prelude::*};
 use bevy_rapier3d::prelude::*;
 
+use crate::physics::SKIP_CORPSES;
+
 /// Manages the FPS controllers. Executes in `PreUpdate`, after bevy's internal
 /// input processing is finished.
 ///
@@ -284,7 +286,8 @@ pub fn fps_controller_move(
                 // Better than a ray cast as it handles when you are near the edge of a surface
                 let filter = QueryFilter::default()
                     .exclude_rigid_body(entity)
-                    .exclude_sensors();
+                    .exclude_sensors()
+                    .groups(SKIP_CORPSES);
                 let ground_cast = physics_context.cast_shape(
                     transform.translation,
                     transform.rotation,
@@ -522,7 +525,9 @@ fn overhang_component(
     // If there is a ledge in front of us we will hit the edge of it
     // We can use the normal of the hit to subtract off the component that is overhanging
     let cast_capsule = Collider::capsule(Vec3::Y * 0.25, -Vec3::Y * 0.25, 0.01);
-    let filter = QueryFilter::default().exclude_rigid_body(entity);
+    let filter = QueryFilter::default()
+        .exclude_rigid_body(entity)
+        .groups(SKIP_CORPSES);
     let collider_offset = collider_y_offset(collider);
     let future_position = transform.translation - collider_offset + velocity * dt;
     let cast = physics_context.cast_shape(
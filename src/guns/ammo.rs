@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+
+use crate::{
+    audio::AudioAssets,
+    character_controller::Player,
+    minimal_kira_audio::{sound_data, KiraAudioManager, KiraSoundData, KiraTrackHandle},
+    SfxTrack,
+};
+
+use super::{inventory::WeaponInventory, weapon_def::WeaponDef, Gun};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReloadState {
+    #[default]
+    Ready,
+    Reloading {
+        started: f32,
+    },
+}
+
+/// Magazine and reserve ammo of a gun. Lives on the gun entity so it is kept while holstered.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct GunAmmo {
+    pub magazine: u32,
+    pub reserve: u32,
+    pub reload: ReloadState,
+}
+
+impl GunAmmo {
+    pub fn full(def: &WeaponDef) -> Self {
+        Self {
+            magazine: def.ammo.magazine_size,
+            reserve: def.ammo.start_reserve,
+            reload: ReloadState::Ready,
+        }
+    }
+
+    pub fn reloading(&self) -> bool {
+        matches!(self.reload, ReloadState::Reloading { .. })
+    }
+
+    pub fn can_fire(&self) -> bool {
+        self.magazine > 0 && !self.reloading()
+    }
+
+    /// Ratio 0..1 of how far along the reload is
+    pub fn reload_progress(&self, def: &WeaponDef, t: f32) -> Option<f32> {
+        match self.reload {
+            ReloadState::Ready => None,
+            ReloadState::Reloading { started } => {
+                Some(((t - started) / def.ammo.reload_time).clamp(0.0, 1.0))
+            }
+        }
+    }
+
+    fn finish_reload(&mut self, def: &WeaponDef) {
+        let take =
+            (def.ammo.magazine_size - self.magazine.min(def.ammo.magazine_size)).min(self.reserve);
+        self.magazine += take;
+        self.reserve -= take;
+        self.reload = ReloadState::Ready;
+    }
+}
+
+/// Reload state machine for the active gun.
+/// Reloads are interrupted (and no ammo is transferred) when switching weapons, dying,
+/// or pulling the trigger while there are still rounds in the magazine.
+pub fn reload_gun(
+    keys: Res<ButtonInput<KeyCode>>,
+    btn: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+    player: Query<(&Player, &WeaponInventory)>,
+    mut guns: Query<(Entity, &Gun, &mut GunAmmo)>,
+    weapon_defs: Res<Assets<WeaponDef>>,
+    audio_stuff: (
+        Option<Res<SfxTrack>>,
+        Res<Assets<KiraSoundData>>,
+        Res<Assets<KiraTrackHandle>>,
+        ResMut<KiraAudioManager>,
+        Res<AudioAssets>,
+    ),
+) {
+    let (sfx, sounds, tracks, mut manager, audio_assets) = audio_stuff;
+    let Ok((player, inventory)) = player.get_single() else {
+        return;
+    };
+    let t = time.elapsed_seconds();
+    let dead = player.health < 0.0;
+    let active_gun = inventory.active_gun();
+
+    for (entity, gun, mut ammo) in &mut guns {
+        let Some(def) = weapon_defs.get(&gun.def) else {
+            continue;
+        };
+        let active = Some(entity) == active_gun && !inventory.switching() && !dead;
+        match ammo.reload {
+            ReloadState::Ready => {
+                if !active || ammo.reserve == 0 || ammo.magazine >= def.ammo.magazine_size {
+                    continue;
+                }
+                // TODO make input configurable
+                let manual = keys.just_pressed(KeyCode::KeyR);
+                let dry_fire = btn.pressed(MouseButton::Left) && ammo.magazine == 0;
+                if manual || dry_fire {
+                    ammo.reload = ReloadState::Reloading { started: t };
+                    if let Some(track) = sfx.as_ref().and_then(|sfx| tracks.get(&sfx.handle)) {
+                        manager
+                            .play(
+                                sound_data(&sounds, &audio_assets.reload)
+                                    .output_destination(&track.0),
+                            )
+                            .unwrap()
+                            .set_volume(0.3, kira::tween::Tween::default());
+                    }
+                }
+            }
+            ReloadState::Reloading { started } => {
+                let interrupted =
+                    !active || (btn.just_pressed(MouseButton::Left) && ammo.magazine > 0);
+                if interrupted {
+                    ammo.reload = ReloadState::Ready;
+                } else if t - started >= def.ammo.reload_time {
+                    ammo.finish_reload(def);
+                }
+            }
+        }
+    }
+}
+
+/// Kills refill the reserve of every carried gun
+pub fn ammo_from_kills(
+    player: Query<&Player, Changed<Player>>,
+    mut guns: Query<(&Gun, &mut GunAmmo)>,
+    weapon_defs: Res<Assets<WeaponDef>>,
+    mut last_kills: Local<u32>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    if player.kills < *last_kills {
+        // Restarted
+        *last_kills = player.kills;
+        return;
+    }
+    let new_kills = player.kills - *last_kills;
+    *last_kills = player.kills;
+    if new_kills == 0 {
+        return;
+    }
+    for (gun, mut ammo) in &mut guns {
+        let Some(def) = weapon_defs.get(&gun.def) else {
+            continue;
+        };
+        ammo.reserve =
+            (ammo.reserve + new_kills * def.ammo.ammo_per_kill).min(def.ammo.max_reserve);
+    }
+}
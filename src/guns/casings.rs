@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    physics::SKIP_CORPSES,
+    util::{EntityRing, RingSlot},
+};
+
+const CASING_GRAVITY: f32 = 13.0;
+/// Collision radius, casings are treated as small spheres
+const CASING_RADIUS: f32 = 0.02;
+/// Fraction of the velocity along the surface normal kept when bouncing
+const CASING_RESTITUTION: f32 = 0.3;
+/// Fraction of the velocity along the surface lost per bounce
+const CASING_FRICTION: f32 = 0.4;
+/// Casings slower than this after touching the ground go to sleep
+const CASING_SLEEP_SPEED: f32 = 0.5;
+
+/// Ejected shell casing. Moved with a ray cast against the level colliders each frame until it comes to rest.
+#[derive(Component)]
+pub struct Casing {
+    velocity: Vec3,
+    /// Tumble in radians per second around the local axes, while in the air
+    spin: Vec3,
+    sleeping: bool,
+    /// Collider that is ignored, so casings don't land on the player
+    exclude: Entity,
+}
+
+/// Fixed size pool of casings. Once the cap (`UserSettings::max_casings`) is reached the oldest casing is reused.
+#[derive(Resource, Default)]
+pub struct CasingPool(EntityRing<Handle<Scene>>);
+
+impl Casing {
+    pub fn new(velocity: Vec3, spin: Vec3, exclude: Entity) -> Self {
+        Self {
+            velocity,
+            spin,
+            sleeping: false,
+            exclude,
+        }
+    }
+}
+
+impl CasingPool {
+    pub fn eject(
+        &mut self,
+        commands: &mut Commands,
+        cap: usize,
+        scene: &Handle<Scene>,
+        transform: Transform,
+        casing: Casing,
+    ) {
+        match self.0.next(commands, cap) {
+            Some(RingSlot::Reuse(mut ecmds, pooled_scene)) => {
+                ecmds.insert((transform, casing));
+                // Changing the scene respawns it, so only do it when a different weapon ejected the casing
+                if pooled_scene != scene {
+                    ecmds.insert(scene.clone());
+                    *pooled_scene = scene.clone();
+                }
+            }
+            Some(RingSlot::Spawn) => {
+                let entity = spawn_casing(commands, scene, transform, casing);
+                self.0.push(entity, scene.clone());
+            }
+            None => (),
+        }
+    }
+
+    /// Despawns every casing, for restarting the level
+    pub fn despawn_all(&mut self, commands: &mut Commands) {
+        self.0.despawn_all(commands);
+    }
+}
+
+fn spawn_casing(
+    commands: &mut Commands,
+    scene: &Handle<Scene>,
+    transform: Transform,
+    casing: Casing,
+) -> Entity {
+    commands
+        .spawn((
+            SceneBundle {
+                scene: scene.clone(),
+                transform,
+                ..default()
+            },
+            casing,
+        ))
+        .id()
+}
+
+pub fn update_casings(
+    mut casings: Query<(&mut Transform, &mut Casing)>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (mut trans, mut casing) in &mut casings {
+        if casing.sleeping {
+            continue;
+        }
+        casing.velocity.y -= CASING_GRAVITY * dt;
+        let step = casing.velocity * dt;
+        let step_len = step.length();
+        if step_len == 0.0 {
+            continue;
+        }
+        let hit = rapier_context.cast_ray_and_get_normal(
+            trans.translation,
+            step / step_len,
+            step_len + CASING_RADIUS,
+            true,
+            QueryFilter::default()
+                .exclude_sensors()
+                .exclude_collider(casing.exclude)
+                // Corpses sink away, casings would be left floating
+                .groups(SKIP_CORPSES),
+        );
+        let Some((_, hit)) = hit else {
+            trans.translation += step;
+            let spin = casing.spin * dt;
+            trans.rotate_local_x(spin.x);
+            trans.rotate_local_y(spin.y);
+            trans.rotate_local_z(spin.z);
+            continue;
+        };
+
+        trans.translation = hit.point + hit.normal * CASING_RADIUS;
+        let normal_speed = casing.velocity.dot(hit.normal);
+        if normal_speed < 0.0 {
+            let normal_vel = hit.normal * normal_speed;
+            let tangent_vel = casing.velocity - normal_vel;
+            casing.velocity =
+                tangent_vel * (1.0 - CASING_FRICTION) - normal_vel * CASING_RESTITUTION;
+        }
+        casing.spin *= 0.5;
+
+        if casing.velocity.length() < CASING_SLEEP_SPEED && hit.normal.y > 0.5 {
+            casing.sleeping = true;
+            casing.velocity = Vec3::ZERO;
+            // Lie flat on the surface, keeping the heading
+            let (yaw, _, _) = trans.rotation.to_euler(EulerRot::YXZ);
+            trans.rotation =
+                Quat::from_rotation_arc(Vec3::Y, hit.normal) * Quat::from_rotation_y(yaw);
+        }
+    }
+}
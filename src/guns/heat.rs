@@ -0,0 +1,78 @@
+use bevy::prelude::*;
+
+use crate::util::all_children;
+
+use super::{weapon_def::WeaponDef, Gun, LMGRotateyBoi};
+
+/// Barrel mesh with its own copy of the material so the glow doesn't leak to other guns using the same model
+#[derive(Component)]
+pub struct HotBarrelMesh(pub Entity);
+
+/// Cools down every gun, including holstered ones, and ends the overheat lockout
+pub fn cool_guns(mut guns: Query<&mut Gun>, weapon_defs: Res<Assets<WeaponDef>>, time: Res<Time>) {
+    let t = time.elapsed_seconds();
+    let dt = time.delta_seconds();
+    for mut gun in &mut guns {
+        let Some(heat_def) = weapon_defs.get(&gun.def).and_then(|def| def.heat) else {
+            continue;
+        };
+        if gun.overheated {
+            gun.heat -= dt * heat_def.overheat_cool_rate;
+            if gun.heat <= heat_def.recover_threshold {
+                gun.overheated = false;
+            }
+        } else if t - gun.last_shot_time > heat_def.cool_delay {
+            gun.heat -= dt * heat_def.cool_rate;
+        }
+        gun.heat = gun.heat.clamp(0.0, 1.0);
+    }
+}
+
+pub fn setup_hot_barrel(
+    mut commands: Commands,
+    barrels: Query<(Entity, &LMGRotateyBoi), Added<LMGRotateyBoi>>,
+    children_query: Query<&Children>,
+    mesh_materials: Query<&Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, barrel) in &barrels {
+        let Ok(children) = children_query.get(entity) else {
+            continue;
+        };
+        all_children(children, &children_query, &mut |child| {
+            if let Ok(material_h) = mesh_materials.get(child) {
+                if let Some(material) = materials.get(material_h).cloned() {
+                    commands
+                        .entity(child)
+                        .insert((materials.add(material), HotBarrelMesh(barrel.0)));
+                }
+            }
+        });
+    }
+}
+
+/// Only touches materials whose glow changed, getting one mutably re-uploads it
+pub fn tint_hot_barrel(
+    barrel_meshes: Query<(&Handle<StandardMaterial>, &HotBarrelMesh)>,
+    guns: Query<&Gun>,
+    weapon_defs: Res<Assets<WeaponDef>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (material_h, barrel_mesh) in &barrel_meshes {
+        let Ok(gun) = guns.get(barrel_mesh.0) else {
+            continue;
+        };
+        let Some(heat_def) = weapon_defs.get(&gun.def).and_then(|def| def.heat) else {
+            continue;
+        };
+        let emissive = heat_def.barrel_emissive(gun.heat);
+        if materials
+            .get(material_h)
+            .is_some_and(|material| material.emissive != emissive)
+        {
+            if let Some(material) = materials.get_mut(material_h) {
+                material.emissive = emissive;
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_egui::EguiContexts;
+
+use crate::character_controller::Player;
+
+use super::Gun;
+
+/// Offset from the rest position a holstered gun moves to before it is hidden
+pub const HOLSTER_OFFSET: Vec3 = Vec3::new(0.0, -1.2, 0.6);
+/// How close (in meters) the gun needs to get to HOLSTER_OFFSET before the next one is drawn
+const HOLSTER_DONE_DIST: f32 = 0.05;
+
+const SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// Weapons carried by the [`Player`]. Each slot is a gun entity with its own [`Gun`] state.
+#[derive(Component, Default)]
+pub struct WeaponInventory {
+    pub slots: Vec<Entity>,
+    pub active: usize,
+    /// Slot that will be drawn once the active gun is holstered
+    pub pending: Option<usize>,
+}
+
+impl WeaponInventory {
+    pub fn active_gun(&self) -> Option<Entity> {
+        self.slots.get(self.active).copied()
+    }
+
+    pub fn switching(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Start holstering the active gun, the slot in `slot` is drawn after
+    pub fn select(&mut self, slot: usize) {
+        if slot >= self.slots.len() {
+            return;
+        }
+        if slot == self.active {
+            // Switching back to the gun that is being holstered cancels the switch
+            self.pending = None;
+        } else {
+            self.pending = Some(slot);
+        }
+    }
+
+    pub fn cycle(&mut self, step: i32) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let from = self.pending.unwrap_or(self.active) as i32;
+        let slot = (from + step).rem_euclid(self.slots.len() as i32);
+        self.select(slot as usize);
+    }
+}
+
+pub fn weapon_switch_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut contexts: EguiContexts,
+    mut player: Query<(&Player, &mut WeaponInventory)>,
+) {
+    let scroll: f32 = mouse_wheel.read().map(|e| e.y).sum();
+    if contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    let Ok((player, mut inventory)) = player.get_single_mut() else {
+        return;
+    };
+    if player.health < 0.0 {
+        return;
+    }
+    for (slot, key) in SLOT_KEYS.iter().enumerate() {
+        if keys.just_pressed(*key) {
+            inventory.select(slot);
+        }
+    }
+    if scroll > 0.0 {
+        inventory.cycle(-1);
+    } else if scroll < 0.0 {
+        inventory.cycle(1);
+    }
+}
+
+/// Holsters the active gun when a switch is pending and draws the next one once it is out of view
+pub fn update_holster(
+    mut player: Query<&mut WeaponInventory>,
+    mut guns: Query<(&mut Gun, &mut Visibility)>,
+) {
+    let Ok(mut inventory) = player.get_single_mut() else {
+        return;
+    };
+    let Some(active_gun) = inventory.active_gun() else {
+        return;
+    };
+    let Ok((mut gun, mut gun_vis)) = guns.get_mut(active_gun) else {
+        return;
+    };
+    let Some(pending) = inventory.pending else {
+        gun.holstered = false;
+        return;
+    };
+    gun.holstered = true;
+    if gun.offset.distance(HOLSTER_OFFSET) > HOLSTER_DONE_DIST {
+        return;
+    }
+    *gun_vis = Visibility::Hidden;
+
+    inventory.active = pending;
+    inventory.pending = None;
+    if let Ok((mut next_gun, mut next_gun_vis)) = guns.get_mut(inventory.slots[pending]) {
+        next_gun.holstered = false;
+        next_gun.offset = HOLSTER_OFFSET;
+        *next_gun_vis = Visibility::Visible;
+    }
+}
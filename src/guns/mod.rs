@@ -3,160 +3,272 @@ use std::{
     f32::consts::{PI, TAU},
 };
 
+use ammo::{ammo_from_kills, reload_gun, GunAmmo};
 use bevy::{core::FrameCount, math::*, prelude::*, render::view::NoFrustumCulling};
 use bevy_asset_loader::asset_collection::AssetCollection;
 use bevy_egui::EguiContexts;
+use bevy_rapier3d::prelude::*;
+use casings::{update_casings, Casing, CasingPool};
+use heat::{cool_guns, setup_hot_barrel, tint_hot_barrel};
+use inventory::{update_holster, weapon_switch_input, WeaponInventory, HOLSTER_OFFSET};
+use projectile::{spawn_projectile, update_projectiles, Projectile};
+use weapon_def::{FireMode, WeaponDef, WeaponDefLoader};
 
 use crate::{
-    audio::AudioAssets,
     character_controller::{manage_cursor, Player},
+    damage::{DamageEvent, DamageKind, Damageable},
+    decals::DecalEvent,
+    difficulty::ShotEvent,
     fps_controller::RenderPlayer,
     hash_noise,
     menu::{menu_ui, UserSettings},
     mesh_assets::MeshAssets,
     minimal_kira_audio::{sound_data, KiraAudioManager, KiraSoundData, KiraTrackHandle},
-    units::{plum::PlumUnit, spider::SpiderUnit},
+    physics::{LevelCollider, SKIP_CORPSES},
+    units::{
+        hit_volumes::{build_hit_volume_bvh, HitVolumeBvh},
+        perception::GunfireEvent,
+    },
     util::{propagate_to_name, PropagateDefault, PropagateToName},
-    GameLoading, SfxTrack, ShaderCompSpawn, LEVEL_TRANSITION_HEIGHT,
+    GameLoading, SfxTrack, ShaderCompSpawn,
 };
 
+pub mod ammo;
+pub mod casings;
+pub mod heat;
+pub mod inventory;
+pub mod projectile;
+pub mod weapon_def;
+
+/// Shots don't hit anything further than this
+const MAX_SHOT_DISTANCE: f32 = 2000.0;
+const BULLET_DECAL_SIZE: f32 = 0.25;
+
 #[derive(AssetCollection, Resource)]
 pub struct GunSceneAssets {
-    #[asset(path = "models/guns/lmg.gltf#Scene0")]
-    pub lmg: Handle<Scene>,
+    /// Starting weapons, in slot order
+    #[asset(
+        paths(
+            "weapons/lmg.weapon.ron",
+            "weapons/carbine.weapon.ron",
+            "weapons/launcher.weapon.ron"
+        ),
+        collection(typed)
+    )]
+    pub weapons: Vec<Handle<WeaponDef>>,
     #[asset(path = "models/guns/lmg_bullet.gltf#Scene0")]
     pub lmg_bullet: Handle<Scene>,
-    #[asset(path = "models/guns/lmg_bullet_jacket.gltf#Scene0")]
-    pub lmg_bullet_jacket: Handle<Scene>,
 }
 
 pub struct GunsPlugin;
 impl Plugin for GunsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            (
-                position_lmg,
-                mark_rotate_part,
-                fire_gun,
-                update_blood_splatter,
+        app.init_asset::<WeaponDef>()
+            .init_asset_loader::<WeaponDefLoader>()
+            .add_systems(
+                Update,
+                (
+                    weapon_switch_input,
+                    update_holster,
+                    position_lmg,
+                    reload_gun,
+                    cool_guns,
+                    fire_gun,
+                    update_projectiles,
+                    ammo_from_kills,
+                    tint_hot_barrel,
+                    update_blood_splatter,
+                )
+                    .chain()
+                    .run_if(in_state(GameLoading::Loaded))
+                    .after(manage_cursor)
+                    .after(build_hit_volume_bvh)
+                    .before(menu_ui),
             )
-                .run_if(in_state(GameLoading::Loaded))
-                .after(manage_cursor)
-                .before(menu_ui),
-        )
-        .add_systems(Update, update_bullet.run_if(in_state(GameLoading::Loaded)))
-        .add_systems(Update, propagate_to_name::<LMGMuzzleFlashMesh>)
-        .add_systems(
-            OnEnter(GameLoading::Loaded),
-            (shadercomp_gun_misc, spawn_gun),
-        );
+            .init_resource::<CasingPool>()
+            .add_systems(Update, update_casings.run_if(in_state(GameLoading::Loaded)))
+            .add_systems(
+                Update,
+                (
+                    propagate_to_name::<LMGMuzzleFlashMesh>,
+                    propagate_to_name::<LMGRotateyBoi>,
+                    setup_hot_barrel,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                OnEnter(GameLoading::Loaded),
+                (shadercomp_gun_misc, spawn_guns),
+            );
     }
 }
 
+/// Per weapon state. Each weapon in the [`WeaponInventory`] has its own entity so this is kept while holstered.
 #[derive(Component, Default)]
-pub struct GunLMG {
+pub struct Gun {
+    pub def: Handle<WeaponDef>,
     offset: Vec3,
+    /// Spin up ratio 0..1
+    rotate_speed: f32,
+    last_shot_time: f32,
+    fire_ready: bool,
+    vis_started: f32,
+    /// Gun moves towards HOLSTER_OFFSET instead of its rest position
+    holstered: bool,
+    /// Barrel heat 0..1
+    heat: f32,
+    /// Locked out from firing until cooled down
+    overheated: bool,
+}
+
+impl Gun {
+    pub fn heat(&self) -> f32 {
+        self.heat
+    }
+
+    pub fn overheated(&self) -> bool {
+        self.overheated
+    }
+
+    /// Back to a cold barrel, for restarts
+    pub fn reset_heat(&mut self) {
+        self.heat = 0.0;
+        self.overheated = false;
+    }
 }
 
+/// Parts reference the gun entity they belong to
 #[derive(Component)]
-pub struct LMGMuzzleFlashLight;
+pub struct LMGMuzzleFlashLight(pub Entity);
 #[derive(Component, Clone)]
-pub struct LMGMuzzleFlashMesh;
+pub struct LMGMuzzleFlashMesh(pub Entity);
 
-#[derive(Component, Default)]
-pub struct LMGRotateyBoi {
-    rotate_speed: f32,
-}
+#[derive(Component, Clone)]
+pub struct LMGRotateyBoi(pub Entity);
 
-fn mark_rotate_part(
+fn spawn_guns(
     mut commands: Commands,
-    entities: Query<(Entity, &Transform, &Name)>,
-    mut done: Local<bool>,
+    gun_assets: Res<GunSceneAssets>,
+    weapon_defs: Res<Assets<WeaponDef>>,
+    player: Query<Entity, With<Player>>,
 ) {
-    if *done {
+    let Ok(player_entity) = player.get_single() else {
         return;
+    };
+    let mut slots = Vec::new();
+    for (i, def_handle) in gun_assets.weapons.iter().enumerate() {
+        let def = weapon_defs.get(def_handle).unwrap();
+        let active = i == 0;
+        slots.push(spawn_gun(&mut commands, def_handle, def, active));
     }
-    for (entity, _trans, name) in &entities {
-        if name.contains("BARREL") {
-            commands.entity(entity).insert(LMGRotateyBoi::default());
-            *done = true;
-            dbg!("gun_trans");
-            return;
-        }
-    }
+    commands
+        .entity(player_entity)
+        .insert(WeaponInventory { slots, ..default() });
 }
 
-fn spawn_gun(mut commands: Commands, gun_assets: Res<GunSceneAssets>) {
-    commands
-        .spawn((
-            SceneBundle {
-                scene: gun_assets.lmg.clone(),
-                transform: Transform::from_xyz(0.0, 0.0, 0.0),
-                ..default()
+fn spawn_gun(
+    commands: &mut Commands,
+    def_handle: &Handle<WeaponDef>,
+    def: &WeaponDef,
+    active: bool,
+) -> Entity {
+    let mut ecmds = commands.spawn((
+        SceneBundle {
+            scene: def.scene.clone(),
+            transform: Transform::from_xyz(0.0, 0.0, 0.0),
+            visibility: if active {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
             },
-            GunLMG::default(),
-            PropagateToName(LMGMuzzleFlashMesh, Cow::Borrowed("MUZZLE_FLASH")),
+            ..default()
+        },
+        Gun {
+            def: def_handle.clone(),
+            offset: if active { Vec3::ZERO } else { HOLSTER_OFFSET },
+            holstered: !active,
+            ..default()
+        },
+        GunAmmo::full(def),
+    ));
+    let gun_entity = ecmds.id();
+    ecmds
+        .insert((
+            PropagateToName(
+                LMGMuzzleFlashMesh(gun_entity),
+                Cow::Borrowed("MUZZLE_FLASH"),
+            ),
+            PropagateToName(LMGRotateyBoi(gun_entity), Cow::Borrowed("BARREL")),
         ))
         .with_children(|cmd| {
             cmd.spawn((
                 PointLightBundle {
                     point_light: PointLight {
                         shadows_enabled: false,
-                        intensity: 100000.0,
+                        intensity: 0.0,
                         //inner_angle: 0.1,
                         //outer_angle: PI / 2.0,
-                        range: 100.0,
+                        range: def.muzzle_flash.range,
                         radius: 0.0,
-                        color: Color::srgb(1.0, 0.7, 0.5),
+                        color: def.muzzle_flash.color(),
                         ..default()
                     },
-                    transform: Transform::from_xyz(-0.17, 0.17, -0.5)
+                    transform: Transform::from_translation(def.muzzle_flash.offset())
                         .looking_at(-Vec3::Z * 10.0, Vec3::Y),
                     ..default()
                 },
-                LMGMuzzleFlashLight,
+                LMGMuzzleFlashLight(gun_entity),
             ));
         });
+    gun_entity
 }
 
 fn position_lmg(
-    mut gun: Query<(&mut Transform, &mut GunLMG)>,
-    player_camera: Query<&Transform, (With<RenderPlayer>, Without<GunLMG>)>,
+    mut guns: Query<(&mut Transform, &mut Gun)>,
+    player_camera: Query<&Transform, (With<RenderPlayer>, Without<Gun>)>,
+    weapon_defs: Res<Assets<WeaponDef>>,
     time: Res<Time>,
 ) {
-    let Ok((mut gun_trans, mut gun)) = gun.get_single_mut() else {
-        return;
-    };
     let Ok(player_cam_trans) = player_camera.get_single() else {
         return;
     };
     let player_mat = player_cam_trans.compute_matrix();
-    gun.offset = gun
-        .offset
-        .lerp(Vec3::ZERO, (time.delta_seconds() * 10.0).clamp(0.0, 1.0));
-    gun_trans.rotation = player_cam_trans.rotation;
-
-    gun_trans.translation = player_mat
-        .transform_point3a(Vec3A::new(0.4, -0.2, -1.6) + Vec3A::from(gun.offset))
-        .into();
+    for (mut gun_trans, mut gun) in &mut guns {
+        let Some(def) = weapon_defs.get(&gun.def) else {
+            continue;
+        };
+        let rest_offset = if gun.holstered {
+            HOLSTER_OFFSET
+        } else {
+            Vec3::ZERO
+        };
+        gun.offset = gun.offset.lerp(
+            rest_offset,
+            (time.delta_seconds() * def.recoil.recover_speed).clamp(0.0, 1.0),
+        );
+        gun_trans.rotation = player_cam_trans.rotation;
+
+        gun_trans.translation = player_mat
+            .transform_point3a(Vec3A::from(def.view_offset()) + Vec3A::from(gun.offset))
+            .into();
+    }
 }
 
 pub fn fire_gun(
     mut commands: Commands,
     btn: Res<ButtonInput<MouseButton>>,
     mut contexts: EguiContexts,
-    mut gun_rot: Query<(&mut Transform, &mut LMGRotateyBoi)>,
+    mut gun_rot: Query<(&mut Transform, &LMGRotateyBoi)>,
     mut gun_muzzle: Query<
-        (&mut PointLight, &mut LMGMuzzleFlashLight),
+        (&mut PointLight, &LMGMuzzleFlashLight),
         (
             Without<LMGRotateyBoi>,
-            Without<GunLMG>,
+            Without<Gun>,
             Without<LMGMuzzleFlashMesh>,
         ),
     >,
     mut gun: Query<
-        (&mut GunLMG, &mut Visibility, &GlobalTransform),
+        (&mut Gun, &mut GunAmmo, &mut Visibility, &GlobalTransform),
         (
             Without<LMGRotateyBoi>,
             Without<LMGMuzzleFlashLight>,
@@ -164,62 +276,88 @@ pub fn fire_gun(
         ),
     >,
     mut muzzle_flash_mesh: Query<
-        &mut Visibility,
+        (&mut Visibility, &LMGMuzzleFlashMesh),
         (
-            With<LMGMuzzleFlashMesh>,
             Without<LMGRotateyBoi>,
             Without<LMGMuzzleFlashLight>,
-            Without<GunLMG>,
+            Without<Gun>,
         ),
     >,
-    mut fire_ready: Local<bool>,
-    gun_assets: Res<GunSceneAssets>,
-    mut vis_started: Local<f32>,
-    mut spiders: Query<(&GlobalTransform, &mut SpiderUnit)>,
-    mut plums: Query<(&GlobalTransform, &mut PlumUnit)>,
+    weapon_defs: Res<Assets<WeaponDef>>,
+    damageables: Query<&Damageable>,
+    mut damage_events: EventWriter<DamageEvent>,
     player_camera: Query<
-        (&mut Player, &Transform),
+        (Entity, &Player, &Transform, &RenderPlayer, &WeaponInventory),
         (
             With<RenderPlayer>,
             Without<LMGMuzzleFlashMesh>,
             Without<LMGRotateyBoi>,
             Without<LMGMuzzleFlashLight>,
-            Without<GunLMG>,
+            Without<Gun>,
         ),
     >,
     mesh_assets: Res<MeshAssets>,
-    misc: (Res<FrameCount>, Res<UserSettings>, Res<Time>),
+    hit_stuff: (
+        Res<HitVolumeBvh>,
+        Res<RapierContext>,
+        Query<(), With<LevelCollider>>,
+        EventWriter<DecalEvent>,
+    ),
+    shot_events: (EventWriter<ShotEvent>, EventWriter<GunfireEvent>),
+    misc: (
+        Res<FrameCount>,
+        Res<UserSettings>,
+        Res<Time>,
+        ResMut<CasingPool>,
+    ),
     audio_stuff: (
         Option<Res<SfxTrack>>,
         Res<Assets<KiraSoundData>>,
         ResMut<Assets<KiraTrackHandle>>,
         ResMut<KiraAudioManager>,
-        Res<AudioAssets>,
     ),
 ) {
-    let (frame, settings, time) = misc;
-    let (sfx, sounds, tracks, mut manager, audio_assets) = audio_stuff;
+    let (frame, settings, time, mut casing_pool) = misc;
+    let (hit_volumes, rapier_context, level_colliders, mut decal_events) = hit_stuff;
+    let (mut shot_events, mut gunfire_events) = shot_events;
+    let (sfx, sounds, tracks, mut manager) = audio_stuff;
     if contexts.ctx_mut().wants_pointer_input() {
         return;
     }
-    let Ok((player, player_cam_trans)) = player_camera.get_single() else {
+    let Ok((player_entity, player, player_cam_trans, player_render, inventory)) =
+        player_camera.get_single()
+    else {
         return;
     };
-    let Ok((mut gun_rot_trans, mut props)) = gun_rot.get_single_mut() else {
+    let Some(active_gun) = inventory.active_gun() else {
         return;
     };
-    let Ok((mut gun_muzzle_light, mut _muzzle_props)) = gun_muzzle.get_single_mut() else {
+    let Ok((mut gun, mut ammo, mut gun_vis, gun_global_trans)) = gun.get_mut(active_gun) else {
         return;
     };
-    let Ok((mut gun, mut gun_vis, gun_global_trans)) = gun.get_single_mut() else {
+    let mut gun_muzzle_light = None;
+    for (mut light, part) in &mut gun_muzzle {
+        if part.0 == active_gun {
+            gun_muzzle_light = Some(light);
+        } else {
+            light.intensity = 0.0;
+        }
+    }
+    let Some(mut gun_muzzle_light) = gun_muzzle_light else {
         return;
     };
-    let Ok(mut muzzle_flash_mesh_vis) = muzzle_flash_mesh.get_single_mut() else {
+    let Some((mut muzzle_flash_mesh_vis, _)) = muzzle_flash_mesh
+        .iter_mut()
+        .find(|(_, part)| part.0 == active_gun)
+    else {
         return;
     };
     let Some(sfx) = sfx else {
         return;
     };
+    let Some(def) = weapon_defs.get(&gun.def) else {
+        return;
+    };
 
     let dead = player.health < 0.0;
 
@@ -232,161 +370,215 @@ pub fn fire_gun(
     let frame = frame.0;
     let t = time.elapsed_seconds();
     let dt = time.delta_seconds();
-    let max_rotate_speed = 12.0;
-    let min_fire_ratio = 0.0;
-    let ramp_up_speed = 0.1;
-    let ramp_down_speed = 0.7;
-    let rotate_offset = 0.1;
+    let spin = def.spin;
     gun_muzzle_light.intensity = 0.0;
 
     // TODO make input configurable
-    let trigger_pressed = btn.pressed(MouseButton::Left) && !dead;
+    let trigger_pressed = btn.pressed(MouseButton::Left) && !dead && !inventory.switching();
 
     if trigger_pressed {
-        props.rotate_speed += dt * ramp_up_speed;
+        gun.rotate_speed += dt * spin.ramp_up_speed;
     } else {
-        props.rotate_speed -= dt * ramp_down_speed;
+        gun.rotate_speed -= dt * spin.ramp_down_speed;
     }
-    gun_rot_trans.rotate_local_z(-dt * props.rotate_speed * max_rotate_speed);
-    props.rotate_speed = props.rotate_speed.clamp(0.0, 1.0);
+    gun.rotate_speed = gun.rotate_speed.clamp(0.0, 1.0);
 
-    let barrel_rot = gun_rot_trans.local_y().xy();
-    let fac = (barrel_rot.y.atan2(barrel_rot.x) + PI) / TAU + rotate_offset;
-    let b_fac = (fac * 8.0).fract();
-    let fire_flip_vis = b_fac > 0.8 && b_fac < 1.0;
-    let fire_flip_logic = b_fac > 0.4; // TODO make sure it fires even at low frame rates
+    let can_fire = trigger_pressed
+        && gun.rotate_speed >= spin.min_fire_ratio
+        && ammo.can_fire()
+        && !gun.overheated;
 
-    if !fire_flip_logic {
-        *fire_ready = true;
-    }
+    let mut fire_this_frame = false;
+    let flash_window = match def.fire_mode {
+        FireMode::Rotary {
+            barrels,
+            fire_phase,
+            flash_phase,
+        } => {
+            let Some((mut gun_rot_trans, _)) =
+                gun_rot.iter_mut().find(|(_, part)| part.0 == active_gun)
+            else {
+                return;
+            };
+            gun_rot_trans.rotate_local_z(-dt * gun.rotate_speed * spin.max_rotate_speed);
 
-    let can_fire = trigger_pressed && props.rotate_speed >= min_fire_ratio;
+            let barrel_rot = gun_rot_trans.local_y().xy();
+            let fac = (barrel_rot.y.atan2(barrel_rot.x) + PI) / TAU + spin.rotate_offset;
+            let b_fac = (fac * barrels as f32).fract();
+            let fire_flip_vis = b_fac > flash_phase && b_fac < 1.0;
+            let fire_flip_logic = b_fac > fire_phase; // TODO make sure it fires even at low frame rates
 
-    let mut fire_this_frame = false;
-    if *fire_ready && fire_flip_logic && can_fire {
-        fire_this_frame = true;
-        *fire_ready = false;
-    }
+            if !fire_flip_logic {
+                gun.fire_ready = true;
+            }
+            if gun.fire_ready && fire_flip_logic && can_fire {
+                fire_this_frame = true;
+                gun.fire_ready = false;
+            }
+            fire_flip_vis && can_fire
+        }
+        FireMode::Automatic { rounds_per_second } => {
+            if can_fire && t - gun.last_shot_time >= 1.0 / rounds_per_second {
+                fire_this_frame = true;
+            }
+            t - gun.last_shot_time < def.muzzle_flash.duration
+        }
+        FireMode::SemiAutomatic => {
+            if can_fire && btn.just_pressed(MouseButton::Left) {
+                fire_this_frame = true;
+            }
+            t - gun.last_shot_time < def.muzzle_flash.duration
+        }
+    };
 
-    let max_vis_time = 0.04;
-    if fire_flip_vis && can_fire && t - *vis_started < max_vis_time {
+    if flash_window && t - gun.vis_started < def.muzzle_flash.duration {
         if !settings.disable_muzzle_flash {
-            gun_muzzle_light.intensity = 400000.0;
+            gun_muzzle_light.intensity = def.muzzle_flash.intensity;
+            gun_muzzle_light.color = def.muzzle_flash_color(gun.heat);
             *muzzle_flash_mesh_vis = Visibility::Visible;
         }
-        if *vis_started == f32::MAX {
-            *vis_started = t;
+        if gun.vis_started == f32::MAX {
+            gun.vis_started = t;
         }
     } else {
         *muzzle_flash_mesh_vis = Visibility::Hidden;
     }
-    if !(fire_flip_vis && can_fire) {
-        *vis_started = f32::MAX;
+    if !flash_window {
+        gun.vis_started = f32::MAX;
     }
 
     if fire_this_frame {
+        gun.last_shot_time = t;
+        ammo.magazine -= 1;
+        if let Some(heat_def) = def.heat {
+            gun.heat += heat_def.heat_per_shot;
+            if gun.heat >= 1.0 {
+                gun.heat = 1.0;
+                gun.overheated = true;
+            }
+        }
         if let Some(track) = tracks.get(&sfx.handle) {
             manager
-                .play(sound_data(&sounds, &audio_assets.gun).output_destination(&track.0))
+                .play(sound_data(&sounds, &def.fire_sound).output_destination(&track.0))
                 .unwrap()
-                .set_volume(0.15, kira::tween::Tween::default());
+                .set_volume(def.fire_volume as f64, kira::tween::Tween::default());
         }
 
         let gun_global_mat = gun_global_trans.compute_matrix();
-        let rng_vel = 2.0;
+        let rng_vel = def.casing.velocity_jitter;
 
-        let offset_strength = 1.0 - props.rotate_speed.clamp(0.0, 1.0);
+        let recoil = def.recoil;
+        let offset_strength = 1.0 - gun.rotate_speed.clamp(0.0, 1.0);
         gun.offset += vec3(
-            (hash_noise(frame, 0, 0) * 2.0 - 1.0) * 0.01,
-            (hash_noise(frame, 1, 0) * 2.0 - 1.0) * 0.01,
-            (hash_noise(frame, 1, 0) * 2.0 - 1.0) * 0.01 + 0.2,
-        ) * (offset_strength * 0.9 + 0.1);
-
-        commands.spawn((
-            SceneBundle {
-                scene: gun_assets.lmg_bullet_jacket.clone(),
-                transform: Transform::from_translation(
-                    gun_global_mat
-                        .transform_point3a(Vec3A::new(0.8, 0.2, -1.2) + Vec3A::from(gun.offset))
-                        .into(),
-                )
-                .looking_at(
-                    gun_global_mat
-                        .transform_point3a(Vec3A::new(0.0, 0.0, -100.0))
-                        .into(),
-                    Vec3::Y,
-                )
-                .with_scale(Vec3::splat(0.6)),
-                ..default()
-            },
-            LMGBullet {
-                velocity: gun_global_mat
-                    .transform_vector3a(Vec3A::new(
-                        2.0 + hash_noise(frame, 0, 0) * rng_vel,
-                        5.0 + hash_noise(frame, 1, 0) * rng_vel,
-                        0.6 + hash_noise(frame, 2, 0) * rng_vel,
-                    ))
+            (hash_noise(frame, 0, 0) * 2.0 - 1.0) * recoil.jitter,
+            (hash_noise(frame, 1, 0) * 2.0 - 1.0) * recoil.jitter,
+            (hash_noise(frame, 1, 0) * 2.0 - 1.0) * recoil.jitter + recoil.kick,
+        ) * (offset_strength * (1.0 - recoil.min_strength) + recoil.min_strength);
+
+        casing_pool.eject(
+            &mut commands,
+            settings.max_casings,
+            &def.casing_scene,
+            Transform::from_translation(
+                gun_global_mat
+                    .transform_point3a(
+                        Vec3A::from(def.casing.eject_offset()) + Vec3A::from(gun.offset),
+                    )
                     .into(),
-                floor_y: player_cam_trans.translation.y - 1.65,
-            },
-        ));
+            )
+            .looking_at(
+                gun_global_mat
+                    .transform_point3a(Vec3A::new(0.0, 0.0, -100.0))
+                    .into(),
+                Vec3::Y,
+            )
+            .with_scale(Vec3::splat(def.casing.scale)),
+            Casing::new(
+                gun_global_mat
+                    .transform_vector3a(
+                        Vec3A::from(def.casing.velocity())
+                            + Vec3A::new(
+                                hash_noise(frame, 0, 0),
+                                hash_noise(frame, 1, 0),
+                                hash_noise(frame, 2, 0),
+                            ) * rng_vel,
+                    )
+                    .into(),
+                vec3(-5.0, -10.0, 0.0) * (hash_noise(frame, 3, 0) + 0.5),
+                player_render.logical_entity,
+            ),
+        );
 
-        let ray = obvhs::ray::Ray::new_inf(
-            player_cam_trans.translation.into(),
-            (*player_cam_trans.forward()).into(),
+        let shot_origin = player_cam_trans.translation;
+        let shot_dir = *player_cam_trans.forward();
+        gunfire_events.send(GunfireEvent {
+            origin: shot_origin,
+        });
+        if let Some(projectile) = &def.projectile {
+            spawn_projectile(
+                &mut commands,
+                projectile,
+                shot_origin,
+                Projectile {
+                    weapon: gun.def.clone(),
+                    velocity: shot_dir * projectile.speed,
+                    spawned: t,
+                    source: player_entity,
+                    exclude: player_render.logical_entity,
+                },
+            );
+            return;
+        }
+        // Level geometry stops the shot
+        let wall = rapier_context.cast_ray_and_get_normal(
+            shot_origin,
+            shot_dir,
+            MAX_SHOT_DISTANCE,
+            true,
+            QueryFilter::default()
+                .exclude_sensors()
+                .exclude_collider(player_render.logical_entity)
+                .groups(SKIP_CORPSES),
         );
-        let mut hit_count = 0;
-        for (unit_transform, mut unit) in &mut spiders {
-            if hit_count > 3 {
-                // Only damage 3 max units
-                break;
-            }
-            let unit_ws_trans = unit_transform.translation_vec3a();
-            let aabb = obvhs::aabb::Aabb {
-                min: unit_ws_trans - 1.8,
-                max: unit_ws_trans + 1.8,
-            };
-            let t = aabb.intersect_ray(&ray);
-            if t != f32::INFINITY {
-                let hitp = ray.origin + ray.direction * t;
-                commands.spawn((
-                    SceneBundle {
-                        scene: mesh_assets.blood.clone(),
-                        transform: Transform::from_translation(hitp.into())
-                            .looking_at(player_cam_trans.translation, Vec3::Y),
-                        ..default()
-                    },
-                    BloodSplatter(0.0),
-                ));
-                hit_count += 1;
-                unit.health -= 40.0;
+        let max_t = wall.map_or(MAX_SHOT_DISTANCE, |(_, hit)| hit.time_of_impact);
+
+        let hits = hit_volumes.ray_hits(shot_origin, shot_dir, max_t, def.max_hits as usize);
+        shot_events.send(ShotEvent {
+            hit: hits.iter().any(|hit| damageables.contains(hit.unit)),
+        });
+        if hits.len() < def.max_hits as usize {
+            // Shot made it through to the wall
+            if let Some((_, wall_hit)) =
+                wall.filter(|(entity, _)| level_colliders.contains(*entity))
+            {
+                decal_events.send(DecalEvent {
+                    point: wall_hit.point,
+                    normal: wall_hit.normal,
+                    size: BULLET_DECAL_SIZE,
+                });
             }
         }
-        for (unit_transform, mut unit) in &mut plums {
-            if hit_count > 3 {
-                // Only damage 3 max units
-                break;
-            }
-            let unit_ws_trans = unit_transform.translation_vec3a();
-            let aabb = obvhs::aabb::Aabb {
-                min: unit_ws_trans - 2.8,
-                max: unit_ws_trans + 2.8,
-            };
-            let t = aabb.intersect_ray(&ray);
-            if t != f32::INFINITY {
-                let hitp = ray.origin + ray.direction * t;
-                commands.spawn((
-                    SceneBundle {
-                        scene: mesh_assets.blood.clone(),
-                        transform: Transform::from_translation(hitp.into())
-                            .looking_at(player_cam_trans.translation, Vec3::Y),
-                        ..default()
-                    },
-                    BloodSplatter(0.0),
-                ));
-                hit_count += 1;
-                unit.health -= 10.0;
+        for hit in hits {
+            if !damageables.contains(hit.unit) {
+                continue;
             }
+            damage_events.send(DamageEvent {
+                target: hit.unit,
+                amount: def.damage_for(hit.class),
+                kind: DamageKind::Bullet,
+                hit_point: hit.point,
+                zone: Some(hit.zone),
+                source: Some(player_entity),
+            });
+            commands.spawn((
+                SceneBundle {
+                    scene: mesh_assets.blood.clone(),
+                    transform: Transform::from_translation(hit.point)
+                        .looking_at(player_cam_trans.translation, Vec3::Y),
+                    ..default()
+                },
+                BloodSplatter(0.0),
+            ));
         }
     }
 }
@@ -414,60 +606,17 @@ fn update_blood_splatter(
     }
 }
 
-#[derive(Component)]
-pub struct LMGBullet {
-    velocity: Vec3,
-    floor_y: f32,
-}
-
-pub fn update_bullet(
-    mut commands: Commands,
-    mut bullets: Query<(Entity, &mut LMGBullet, &mut Transform)>,
-    time: Res<Time>,
-    player_camera: Query<&Transform, (With<RenderPlayer>, Without<LMGBullet>)>,
-) {
-    let Ok(player_cam_trans) = player_camera.get_single() else {
-        return;
-    };
-    let dt = time.delta_seconds();
-    let iter = bullets.iter_mut();
-    let mut delete_one = iter.len() > 20000;
-    for (entity, mut bullet, mut trans) in iter {
-        if delete_one {
-            commands.entity(entity).despawn_recursive();
-            delete_one = false;
-            continue;
-        }
-        if player_cam_trans.translation.y < LEVEL_TRANSITION_HEIGHT {
-            bullet.floor_y = -220.0;
-        }
-        if trans.translation.y < bullet.floor_y + 0.1 {
-            bullet.velocity.y = 0.0;
-            bullet.velocity.x *= 0.993;
-            bullet.velocity.z *= 0.993;
-            if bullet.velocity.y == 0.0 {
-                trans.rotate_local_y(3.0 * dt * bullet.velocity.x * bullet.velocity.z);
-            } else {
-                trans.rotation = Default::default();
-            }
-            //commands.entity(entity).despawn_recursive(); // Despawn after a while
-        } else {
-            bullet.velocity -= Vec3::Y * 13.0 * dt; // Gravity or whatever
-        }
-        trans.translation += bullet.velocity * dt;
-        if bullet.velocity.y > 0.0 {
-            trans.rotate_local_y(-10.0 * dt);
-            trans.rotate_local_x(-5.0 * dt);
-        }
-    }
-}
-
 fn shadercomp_gun_misc(
     mut commands: Commands,
     assets: Res<GunSceneAssets>,
+    weapon_defs: Res<Assets<WeaponDef>>,
     mesh_assets: Res<MeshAssets>,
 ) {
-    for scene in [assets.lmg_bullet.clone(), assets.lmg_bullet_jacket.clone()] {
+    let mut scenes = vec![assets.lmg_bullet.clone()];
+    for def_handle in &assets.weapons {
+        scenes.push(weapon_defs.get(def_handle).unwrap().casing_scene.clone());
+    }
+    for scene in scenes {
         commands.spawn((
             SceneBundle {
                 scene,
@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    damage::{DamageEvent, DamageKind},
+    difficulty::ShotEvent,
+    mesh_assets::MeshAssets,
+    physics::SKIP_CORPSES,
+    units::{hit_volumes::HitVolumeBvh, Explosion},
+};
+
+use super::weapon_def::{ProjectileDef, WeaponDef};
+
+/// A shot from a weapon with a [`ProjectileDef`], moved and swept against the world every frame
+#[derive(Component, Clone)]
+pub struct Projectile {
+    pub weapon: Handle<WeaponDef>,
+    pub velocity: Vec3,
+    pub spawned: f32,
+    /// Gets the kill credit
+    pub source: Entity,
+    /// Collider the sweep ignores, so the shot doesn't hit whoever fired it
+    pub exclude: Entity,
+}
+
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    def: &ProjectileDef,
+    origin: Vec3,
+    projectile: Projectile,
+) {
+    commands.spawn((
+        SceneBundle {
+            scene: def.scene.clone(),
+            transform: Transform::from_translation(origin)
+                .looking_to(projectile.velocity, Vec3::Y)
+                .with_scale(Vec3::splat(def.scale)),
+            ..default()
+        },
+        projectile,
+    ));
+}
+
+pub fn update_projectiles(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &mut Transform, &mut Projectile)>,
+    weapon_defs: Res<Assets<WeaponDef>>,
+    hit_stuff: (Res<HitVolumeBvh>, Res<RapierContext>),
+    mut damage_events: EventWriter<DamageEvent>,
+    mut shot_events: EventWriter<ShotEvent>,
+    mesh_assets: Res<MeshAssets>,
+    time: Res<Time>,
+) {
+    let (hit_volumes, rapier_context) = hit_stuff;
+    let t = time.elapsed_seconds();
+    let dt = time.delta_seconds();
+    for (entity, mut trans, mut projectile) in &mut projectiles {
+        let Some((def, projectile_def)) = weapon_defs
+            .get(&projectile.weapon)
+            .and_then(|def| Some((def, def.projectile.as_ref()?)))
+        else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        let start = trans.translation;
+        let step = projectile.velocity * dt;
+        let step_len = step.length();
+
+        // Sweep the level first, units are only hit if they are in front of the wall
+        let wall_toi = rapier_context
+            .cast_shape(
+                start,
+                Quat::IDENTITY,
+                step,
+                &Collider::ball(projectile_def.radius),
+                ShapeCastOptions::with_max_time_of_impact(1.0),
+                QueryFilter::default()
+                    .exclude_sensors()
+                    .exclude_collider(projectile.exclude)
+                    .groups(SKIP_CORPSES),
+            )
+            .map(|(_, hit)| hit.time_of_impact);
+        let unit_hit = if step_len > 0.0 {
+            hit_volumes
+                .ray_hits(
+                    start,
+                    step / step_len,
+                    step_len * wall_toi.unwrap_or(1.0),
+                    1,
+                )
+                .into_iter()
+                .next()
+        } else {
+            None
+        };
+        let impact = match (unit_hit, wall_toi) {
+            (Some(hit), _) => Some(hit.point),
+            (None, Some(toi)) => Some(start + step * toi),
+            (None, None) => None,
+        };
+        let expired = t - projectile.spawned > projectile_def.lifetime;
+
+        let Some(impact) = impact.or(expired.then_some(start)) else {
+            trans.translation += step;
+            projectile.velocity.y -= projectile_def.gravity * dt;
+            let look_dir = projectile.velocity;
+            trans.look_to(look_dir, Vec3::Y);
+            continue;
+        };
+
+        commands.entity(entity).despawn_recursive();
+        commands.spawn((
+            SceneBundle {
+                scene: mesh_assets.exp.clone(),
+                transform: Transform::from_translation(impact),
+                ..default()
+            },
+            Explosion(0.0),
+        ));
+
+        if projectile_def.splash_radius > 0.0 {
+            let hits = hit_volumes.units_in_radius(impact, projectile_def.splash_radius);
+            shot_events.send(ShotEvent {
+                hit: !hits.is_empty(),
+            });
+            for hit in hits {
+                let falloff = 1.0 - hit.t / projectile_def.splash_radius;
+                damage_events.send(DamageEvent {
+                    target: hit.unit,
+                    amount: def.damage_for(hit.class) * falloff,
+                    kind: DamageKind::Explosion,
+                    hit_point: hit.point,
+                    zone: None,
+                    source: Some(projectile.source),
+                });
+            }
+        } else if let Some(hit) = unit_hit {
+            shot_events.send(ShotEvent { hit: true });
+            damage_events.send(DamageEvent {
+                target: hit.unit,
+                amount: def.damage_for(hit.class),
+                kind: DamageKind::Bullet,
+                hit_point: hit.point,
+                zone: Some(hit.zone),
+                source: Some(projectile.source),
+            });
+        } else {
+            shot_events.send(ShotEvent { hit: false });
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use anyhow::Result;
+use bevy::asset::io::Reader;
+use bevy::asset::{Asset, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::minimal_kira_audio::KiraSoundData;
+
+/// Which kind of unit a shot hit. Used to look up per target damage in [`WeaponDef::damage`]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+pub enum TargetClass {
+    Spider,
+    Plum,
+    Spitter,
+    Boss,
+}
+
+/// How the trigger turns into shots
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum FireMode {
+    /// Fires each time a barrel passes the firing position, rate follows the spin speed
+    Rotary {
+        barrels: u32,
+        /// Fraction of a barrel step where the shot is fired
+        fire_phase: f32,
+        /// Fraction of a barrel step after which the muzzle flash is visible
+        flash_phase: f32,
+    },
+    /// Fires at a fixed rate while the trigger is held
+    Automatic { rounds_per_second: f32 },
+    /// Fires once per trigger press
+    SemiAutomatic,
+}
+
+/// Barrel spin up/down. For non rotary weapons this acts as a simple warm up.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct SpinDef {
+    pub max_rotate_speed: f32,
+    pub ramp_up_speed: f32,
+    pub ramp_down_speed: f32,
+    /// Spin ratio required before the gun will fire
+    pub min_fire_ratio: f32,
+    pub rotate_offset: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RecoilDef {
+    /// Random offset in each axis per shot
+    pub jitter: f32,
+    /// Backwards kick per shot
+    pub kick: f32,
+    /// Recoil strength when fully spun up (recoil is 1.0 when not spun up at all)
+    pub min_strength: f32,
+    /// How fast the gun returns to its rest position
+    pub recover_speed: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AmmoDef {
+    pub magazine_size: u32,
+    /// Reserve ammo the gun starts with
+    pub start_reserve: u32,
+    pub max_reserve: u32,
+    /// Seconds
+    pub reload_time: f32,
+    /// Added to the reserve for each kill
+    pub ammo_per_kill: u32,
+}
+
+/// Heat is 0..1, reaching 1 overheats the gun until it cools below `recover_threshold`
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct HeatDef {
+    pub heat_per_shot: f32,
+    /// Heat lost per second once `cool_delay` seconds have passed since the last shot
+    pub cool_rate: f32,
+    pub cool_delay: f32,
+    /// Heat lost per second while overheated
+    pub overheat_cool_rate: f32,
+    pub recover_threshold: f32,
+    /// Heat is raised to this power before it is used for tinting
+    pub tint_curve: f32,
+    /// Muzzle flash color at full heat
+    pub hot_flash_color: [f32; 3],
+    pub barrel_glow_color: [f32; 3],
+    pub barrel_glow_strength: f32,
+}
+
+impl HeatDef {
+    pub fn tint(&self, heat: f32) -> f32 {
+        heat.clamp(0.0, 1.0).powf(self.tint_curve)
+    }
+
+    pub fn barrel_emissive(&self, heat: f32) -> LinearRgba {
+        let glow = Vec3::from(self.barrel_glow_color) * self.tint(heat) * self.barrel_glow_strength;
+        LinearRgba::rgb(glow.x, glow.y, glow.z)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct MuzzleFlashDef {
+    pub offset: [f32; 3],
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    /// Max time in seconds the flash stays visible per shot
+    pub duration: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct CasingDef {
+    /// Where the casing is spawned, relative to the gun
+    pub eject_offset: [f32; 3],
+    /// Eject velocity, relative to the gun
+    pub velocity: [f32; 3],
+    /// Random velocity added in each axis
+    pub velocity_jitter: f32,
+    pub scale: f32,
+}
+
+/// Weapons with a projectile fire a shot that travels through the world instead of hitting instantly
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProjectileDef {
+    /// Meters per second
+    pub speed: f32,
+    /// Downwards acceleration in meters per second squared
+    pub gravity: f32,
+    /// Radius of the sweep against the level colliders
+    pub radius: f32,
+    /// Units within this distance of the impact take damage falling off with distance.
+    /// With 0 only the unit that was hit directly is damaged.
+    #[serde(default)]
+    pub splash_radius: f32,
+    /// Seconds until the projectile detonates on its own
+    pub lifetime: f32,
+    pub scale: f32,
+
+    scene_path: String,
+    #[serde(skip)]
+    pub scene: Handle<Scene>,
+}
+
+/// Describes a weapon. Loaded from `.weapon.ron` files so weapons can be added without touching code.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct WeaponDef {
+    pub name: String,
+    pub fire_mode: FireMode,
+    pub spin: SpinDef,
+    pub damage: HashMap<TargetClass, f32>,
+    /// Max number of units a single shot can damage
+    pub max_hits: u32,
+    pub ammo: AmmoDef,
+    /// Guns without heat never overheat
+    #[serde(default)]
+    pub heat: Option<HeatDef>,
+    /// Guns without a projectile are hitscan
+    #[serde(default)]
+    pub projectile: Option<ProjectileDef>,
+    pub recoil: RecoilDef,
+    /// Rest position of the gun relative to the camera
+    pub view_offset: [f32; 3],
+    pub muzzle_flash: MuzzleFlashDef,
+    pub casing: CasingDef,
+    pub fire_volume: f32,
+
+    scene_path: String,
+    casing_scene_path: String,
+    fire_sound_path: String,
+
+    #[serde(skip)]
+    pub scene: Handle<Scene>,
+    #[serde(skip)]
+    pub casing_scene: Handle<Scene>,
+    #[serde(skip)]
+    pub fire_sound: Handle<KiraSoundData>,
+}
+
+impl WeaponDef {
+    pub fn damage_for(&self, class: TargetClass) -> f32 {
+        self.damage.get(&class).copied().unwrap_or(0.0)
+    }
+
+    pub fn view_offset(&self) -> Vec3 {
+        Vec3::from(self.view_offset)
+    }
+
+    /// Muzzle flash color, shifted towards `hot_flash_color` as the gun heats up
+    pub fn muzzle_flash_color(&self, heat: f32) -> Color {
+        let cold = Vec3::from(self.muzzle_flash.color);
+        let color = match &self.heat {
+            Some(heat_def) => cold.lerp(Vec3::from(heat_def.hot_flash_color), heat_def.tint(heat)),
+            None => cold,
+        };
+        Color::srgb(color.x, color.y, color.z)
+    }
+}
+
+impl MuzzleFlashDef {
+    pub fn offset(&self) -> Vec3 {
+        Vec3::from(self.offset)
+    }
+
+    pub fn color(&self) -> Color {
+        Color::srgb(self.color[0], self.color[1], self.color[2])
+    }
+}
+
+impl CasingDef {
+    pub fn eject_offset(&self) -> Vec3 {
+        Vec3::from(self.eject_offset)
+    }
+
+    pub fn velocity(&self) -> Vec3 {
+        Vec3::from(self.velocity)
+    }
+}
+
+/// Possible errors that can be produced by [`WeaponDefLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum WeaponDefLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON Error](ron::error::SpannedError)
+    #[error("Could not parse RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+/// Asset loader for `.weapon.ron` files.
+#[derive(Default)]
+pub struct WeaponDefLoader;
+
+impl AssetLoader for WeaponDefLoader {
+    type Asset = WeaponDef;
+    type Settings = ();
+    type Error = WeaponDefLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        let mut def = ron::de::from_bytes::<WeaponDef>(&bytes)?;
+        def.scene = load_context.load(def.scene_path.clone());
+        def.casing_scene = load_context.load(def.casing_scene_path.clone());
+        def.fire_sound = load_context.load(def.fire_sound_path.clone());
+        if let Some(projectile) = &mut def.projectile {
+            projectile.scene = load_context.load(projectile.scene_path.clone());
+        }
+        Ok(def)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["weapon.ron"]
+    }
+}
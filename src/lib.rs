@@ -4,14 +4,19 @@ use minimal_kira_audio::KiraTrackHandle;
 pub mod animation;
 pub mod audio;
 pub mod character_controller;
+pub mod damage;
+pub mod decals;
+pub mod difficulty;
 pub mod fps_controller;
 pub mod guns;
 pub mod menu;
 pub mod mesh_assets;
 pub mod minimal_kira_audio;
+pub mod navmesh;
 pub mod physics;
 pub mod units;
 pub mod util;
+pub mod waves;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum GameLoading {
@@ -33,13 +33,23 @@ use character_controller::CharacterController;
 use eldritch_game::audio::spatial::{AudioEmitter, AudioEmitterSet};
 use eldritch_game::audio::AudioAssets;
 use eldritch_game::character_controller::Player;
+use eldritch_game::damage::{DamagePlugin, Damageable};
+use eldritch_game::decals::DecalPlugin;
+use eldritch_game::difficulty::{Difficulty, DifficultyPlugin};
 use eldritch_game::fps_controller::LogicalPlayer;
-use eldritch_game::guns::{GunSceneAssets, GunsPlugin};
+use eldritch_game::guns::ammo::GunAmmo;
+use eldritch_game::guns::inventory::WeaponInventory;
+use eldritch_game::guns::weapon_def::WeaponDef;
+use eldritch_game::guns::{Gun, GunSceneAssets, GunsPlugin};
 use eldritch_game::menu::{menu_ui, MenuPlugin};
 use eldritch_game::mesh_assets::MeshAssets;
+use eldritch_game::navmesh::NavMeshPlugin;
 use eldritch_game::physics::{AddCuboidColliders, AddCuboidSensors};
+use eldritch_game::units::boss::{Boss, BossArena, BossSpawnPoint, BossState};
+use eldritch_game::units::enemy::EnemyArchetype;
 use eldritch_game::units::UnitsPlugin;
 use eldritch_game::util::{propagate_to_name, PropagateToName};
+use eldritch_game::waves::{SpawnPoint, WaveAssets, WavePlugin};
 use eldritch_game::{
     audio, character_controller, minimal_kira_audio, physics, GameLoading, MusicTrack, PlayerStart,
     SfxTrack, ShaderCompSpawn, StartLevel, LEVEL_TRANSITION_HEIGHT,
@@ -116,7 +126,16 @@ fn main() {
             MenuPlugin,
         ));
 
-    app.add_plugins((GameAudioPlugin, UnitsPlugin, GunsPlugin));
+    app.add_plugins((
+        GameAudioPlugin,
+        UnitsPlugin,
+        GunsPlugin,
+        DamagePlugin,
+        DecalPlugin,
+        NavMeshPlugin,
+        WavePlugin,
+        DifficultyPlugin,
+    ));
 
     app.init_state::<GameLoading>()
         .add_plugins(ProgressPlugin::new(GameLoading::AssetLoading))
@@ -129,7 +148,8 @@ fn main() {
             LoadingState::new(GameLoading::AssetLoading2)
                 .continue_to_state(GameLoading::Loaded)
                 .load_collection::<MeshAssets>()
-                .load_collection::<GunSceneAssets>(),
+                .load_collection::<GunSceneAssets>()
+                .load_collection::<WaveAssets>(),
         );
 
     app.add_systems(Startup, setup)
@@ -138,6 +158,9 @@ fn main() {
             Update,
             (
                 propagate_to_name::<PlayerStart>,
+                propagate_to_name::<SpawnPoint>,
+                propagate_to_name::<BossArena>,
+                propagate_to_name::<BossSpawnPoint>,
                 hide_start_level,
                 hud,
                 move_player_to_start,
@@ -213,6 +236,9 @@ fn add_level_props(mut ecmds: EntityCommands) {
         PropagateToName(AddCuboidColliders, Cow::Borrowed("COLLIDER")),
         PropagateToName(AddCuboidSensors, Cow::Borrowed("SENSOR")),
         PropagateToName(PlayerStart, Cow::Borrowed("PLAYER_START")),
+        PropagateToName(SpawnPoint, Cow::Borrowed("SPAWN_POINT")),
+        PropagateToName(BossArena, Cow::Borrowed("BOSS_ARENA")),
+        PropagateToName(BossSpawnPoint, Cow::Borrowed("BOSS_SPAWN")),
     ));
 }
 
@@ -359,10 +385,14 @@ fn setup_egui_style(mut contexts: EguiContexts, mut has_set_style: Local<bool>)
 
 fn hud(
     mut contexts: EguiContexts,
-    mut player: Query<(&mut Transform, &mut Player)>,
+    mut player: Query<(&mut Transform, &mut Player, Option<&WeaponInventory>)>,
+    guns: Query<(&Gun, &GunAmmo)>,
+    weapon_defs: Res<Assets<WeaponDef>>,
+    difficulty: Res<Difficulty>,
+    bosses: Query<(&Damageable, &BossState)>,
     time: Res<Time>,
 ) {
-    let Ok((player_trans, mut player)) = player.get_single_mut() else {
+    let Ok((player_trans, mut player, inventory)) = player.get_single_mut() else {
         return;
     };
 
@@ -391,6 +421,88 @@ fn hud(
         egui::Color32::WHITE,
     );
 
+    if let Some((gun, ammo)) = inventory
+        .and_then(|inventory| inventory.active_gun())
+        .and_then(|entity| guns.get(entity).ok())
+    {
+        if let Some(def) = weapon_defs.get(&gun.def) {
+            let status = match ammo.reload_progress(def, time.elapsed_seconds()) {
+                _ if gun.overheated() => "OVERHEATED".to_string(),
+                Some(progress) => format!("RELOADING {:.0}%", progress * 100.0),
+                None if ammo.magazine == 0 && ammo.reserve == 0 => "NO AMMO".to_string(),
+                None if ammo.magazine == 0 => "RELOAD [R]".to_string(),
+                None => String::new(),
+            };
+            painter.text(
+                egui::Pos2::new(size.width() - 10.0, size.height() - 10.0),
+                egui::Align2::RIGHT_BOTTOM,
+                format!(
+                    "{}\n{:>4} / {:<4}\n{}",
+                    def.name, ammo.magazine, ammo.reserve, status
+                ),
+                egui::FontId {
+                    size: 20.0,
+                    family: egui::FontFamily::Monospace,
+                },
+                egui::Color32::from_rgba_unmultiplied(255, 255, 255, 96),
+            );
+
+            if def.heat.is_some() {
+                let gauge_width = 160.0;
+                let gauge_height = 6.0;
+                let gauge_min = egui::Pos2::new(
+                    size.width() - 10.0 - gauge_width,
+                    size.height() - 90.0 - gauge_height,
+                );
+                painter.rect_filled(
+                    egui::Rect::from_min_size(gauge_min, egui::vec2(gauge_width, gauge_height)),
+                    egui::Rounding::ZERO,
+                    egui::Color32::from_rgba_unmultiplied(255, 255, 255, 24),
+                );
+                let heat_color = if gun.overheated() {
+                    egui::Color32::from_rgba_unmultiplied(255, 40, 0, 160)
+                } else {
+                    egui::Color32::from_rgba_unmultiplied(255, 140, 60, 96)
+                };
+                painter.rect_filled(
+                    egui::Rect::from_min_size(
+                        gauge_min,
+                        egui::vec2(gauge_width * gun.heat(), gauge_height),
+                    ),
+                    egui::Rounding::ZERO,
+                    heat_color,
+                );
+            }
+        }
+    }
+
+    for (damageable, boss) in &bosses {
+        let bar_width = size.width() * 0.5;
+        let bar_height = 12.0;
+        let bar_min = egui::Pos2::new(mid_x - bar_width * 0.5, 40.0);
+        painter.text(
+            egui::Pos2::new(mid_x, bar_min.y - 4.0),
+            egui::Align2::CENTER_BOTTOM,
+            boss.phase().name,
+            egui::FontId {
+                size: 20.0,
+                family: egui::FontFamily::Monospace,
+            },
+            egui::Color32::from_rgba_unmultiplied(255, 255, 255, 160),
+        );
+        painter.rect_filled(
+            egui::Rect::from_min_size(bar_min, egui::vec2(bar_width, bar_height)),
+            egui::Rounding::ZERO,
+            egui::Color32::from_rgba_unmultiplied(255, 255, 255, 24),
+        );
+        let health = (damageable.health / Boss::DAMAGEABLE.health).clamp(0.0, 1.0);
+        painter.rect_filled(
+            egui::Rect::from_min_size(bar_min, egui::vec2(bar_width * health, bar_height)),
+            egui::Rounding::ZERO,
+            egui::Color32::from_rgba_unmultiplied(200, 20, 0, 160),
+        );
+    }
+
     let health = player.health;
     let kills = player.kills;
     if let Some(time_survived) = &mut player.activity_start_time {
@@ -402,8 +514,8 @@ fn hud(
             egui::Pos2::new(10.0, 10.0),
             egui::Align2::LEFT_TOP,
             format!(
-                "HEALTH        {:.1} \nKILLS         {} \nTIME SURVIVED {:.1}",
-                health, kills, time_survived
+                "HEALTH        {:.1} \nKILLS         {} \nTIME SURVIVED {:.1} \nDIFFICULTY    {}",
+                health, kills, time_survived, difficulty.tier_name
             ),
             egui::FontId {
                 size: 20.0,
@@ -7,11 +7,18 @@ use bs13_render::{else_return, BS13ViewTargetSettings};
 use fps_controller::FpsController;
 
 use crate::character_controller::Player;
+use crate::decals::ImpactDecals;
 use crate::fps_controller::{self, LogicalPlayer};
-use crate::guns::LMGBullet;
+use crate::guns::ammo::GunAmmo;
+use crate::guns::weapon_def::WeaponDef;
+use crate::guns::{casings::CasingPool, projectile::Projectile, Gun};
 use crate::minimal_kira_audio::KiraTrackHandle;
+use crate::units::boss::BossUnit;
+use crate::units::corpse::Corpse;
+use crate::units::enemy_projectile::EnemyProjectile;
 use crate::units::plum::PlumUnit;
 use crate::units::spider::SpiderUnit;
+use crate::units::spitter::SpitterUnit;
 use crate::{GameLoading, MusicTrack, PlayerStart, SfxTrack, StartLevel};
 
 pub struct MenuPlugin;
@@ -22,9 +29,23 @@ impl Plugin for MenuPlugin {
     }
 }
 
-#[derive(Resource, Default)]
+#[derive(Resource)]
 pub struct UserSettings {
     pub disable_muzzle_flash: bool,
+    /// Oldest shell casings are reused once this many are in the level
+    pub max_casings: usize,
+    /// Oldest bullet impact decals are reused once this many are in the level
+    pub max_decals: usize,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            disable_muzzle_flash: false,
+            max_casings: 2000,
+            max_decals: 500,
+        }
+    }
 }
 
 pub fn menu_ui(
@@ -34,7 +55,18 @@ pub fn menu_ui(
     mut contexts: EguiContexts,
     mut settings: ResMut<UserSettings>,
     mut view_target_settings: Query<&mut BS13ViewTargetSettings>,
-    stuff_to_despawn: Query<Entity, Or<(With<PlumUnit>, With<SpiderUnit>, With<LMGBullet>)>>,
+    stuff_to_despawn: Query<
+        Entity,
+        Or<(
+            With<PlumUnit>,
+            With<SpiderUnit>,
+            With<SpitterUnit>,
+            With<BossUnit>,
+            With<Projectile>,
+            With<EnemyProjectile>,
+            With<Corpse>,
+        )>,
+    >,
     mut player: Query<&mut Player>,
     mut logical_player: Query<(&mut Transform, &LogicalPlayer)>,
     mut start_level_items: Query<(Entity, &mut Visibility), With<StartLevel>>,
@@ -43,7 +75,14 @@ pub fn menu_ui(
     music: Option<ResMut<MusicTrack>>,
     sfx: Option<ResMut<SfxTrack>>,
     mut tracks: ResMut<Assets<KiraTrackHandle>>,
+    guns: (
+        Query<(&mut Gun, &mut GunAmmo)>,
+        Res<Assets<WeaponDef>>,
+        ResMut<CasingPool>,
+        ResMut<ImpactDecals>,
+    ),
 ) {
+    let (mut guns, weapon_defs, mut casings, mut decals) = guns;
     let Ok(mut player_stats) = player.get_single_mut() else {
         return;
     };
@@ -111,6 +150,10 @@ pub fn menu_ui(
             ui.allocate_space(egui::vec2(width, 40.0));
             ui.label("RENDER SETTINGS");
             ui.checkbox(&mut settings.disable_muzzle_flash, "DISABLE MUZZLE FLASH");
+            ui.add(
+                egui::Slider::new(&mut settings.max_casings, 0..=20000).text("MAX SHELL CASINGS"),
+            );
+            ui.add(egui::Slider::new(&mut settings.max_decals, 0..=5000).text("MAX IMPACT DECALS"));
             ui.add(
                 egui::Slider::new(&mut view_target_settings.render_scale, 0.25..=2.0)
                     .text("RENDER SCALE"),
@@ -159,7 +202,15 @@ pub fn menu_ui(
                 for entity in &stuff_to_despawn {
                     commands.entity(entity).despawn_recursive();
                 }
+                casings.despawn_all(&mut commands);
+                decals.despawn_all(&mut commands);
                 *player_stats = Default::default();
+                for (mut gun, mut ammo) in &mut guns {
+                    gun.reset_heat();
+                    if let Some(def) = weapon_defs.get(&gun.def) {
+                        *ammo = GunAmmo::full(def);
+                    }
+                }
 
                 for (_entity, mut vis) in &mut start_level_items {
                     *vis = Visibility::Visible;
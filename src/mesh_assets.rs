@@ -1,7 +1,37 @@
 use bevy::prelude::*;
 use bevy_asset_loader::asset_collection::AssetCollection;
 
-#[derive(AssetCollection, Resource)]
+use crate::animation::{AnimNotifies, AnimStateMachine};
+
+crate::anim_names! {
+    pub enum FoxClip {
+        Survey = "Survey",
+        Walk = "Walk",
+        Run = "Run",
+    }
+}
+
+crate::anim_names! {
+    /// Clips of the plum and boss model
+    pub enum StabbyClip {
+        Attack = "Attack",
+        FastWalkCycle = "Fast_Walk_Cycle",
+        FastTurningLeft = "Fast_Turning_Left",
+        FastTurningRight = "Fast_Turning_Right",
+    }
+}
+
+crate::anim_names! {
+    /// Clips of the spider and spitter model
+    pub enum StingerClip {
+        Attack = "Attack",
+        WanderingWalkCycle = "Wandering_Walk_Cycle",
+        WanderingTurnLeft = "Wandering_Turn_Left",
+        WanderingTurnRight = "Wandering_Turn_Right",
+    }
+}
+
+#[derive(AssetCollection, Resource, Default)]
 pub struct MeshAssets {
     #[asset(path = "temp/animated/Fox.glb")]
     pub fox_gltf: Handle<Gltf>,
@@ -14,11 +44,15 @@ pub struct MeshAssets {
     pub plum_gltf: Handle<Gltf>,
     #[asset(path = "temp/animated/Stabby_Enemy.gltf#Scene0")]
     pub plum: Handle<Scene>,
+    #[asset(path = "temp/animated/Stabby_Enemy.notifies.ron")]
+    pub plum_notifies: Handle<AnimNotifies>,
 
     #[asset(path = "temp/animated/Stinger_Enemy.gltf")]
     pub spider_gltf: Handle<Gltf>,
     #[asset(path = "temp/animated/Stinger_Enemy.gltf#Scene0")]
     pub spider: Handle<Scene>,
+    #[asset(path = "temp/animated/Stinger_Enemy.notifies.ron")]
+    pub spider_notifies: Handle<AnimNotifies>,
 
     #[asset(path = "temp/level_start.gltf#Scene0")]
     pub level_start: Handle<Scene>,
@@ -27,6 +61,15 @@ pub struct MeshAssets {
     #[asset(path = "temp/starting_level.gltf#Scene0")]
     pub starting_level: Handle<Scene>,
 
+    #[asset(path = "units/plum.anim.ron")]
+    pub plum_anim: Handle<AnimStateMachine>,
+    #[asset(path = "units/boss.anim.ron")]
+    pub boss_anim: Handle<AnimStateMachine>,
+    #[asset(path = "units/spider.anim.ron")]
+    pub spider_anim: Handle<AnimStateMachine>,
+    #[asset(path = "units/spitter.anim.ron")]
+    pub spitter_anim: Handle<AnimStateMachine>,
+
     #[asset(path = "models/blood.gltf#Scene0")]
     pub blood: Handle<Scene>,
 
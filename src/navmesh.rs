@@ -0,0 +1,710 @@
+use std::{cmp::Ordering, collections::BinaryHeap, time::Instant};
+
+use bevy::{
+    math::{vec2, vec3},
+    prelude::*,
+    render::primitives::Aabb,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{physics::LevelCollider, GameLoading};
+
+/// Width of a navmesh cell in meters
+const NAV_CELL_SIZE: f32 = 2.5;
+/// Cell size is increased if the level would need more cells than this
+const NAV_MAX_COLUMNS: usize = 1 << 20;
+/// Surfaces steeper than this (as the y of the surface normal) aren't walkable
+const WALKABLE_NORMAL_Y: f32 = 0.7;
+const AGENT_HEIGHT: f32 = 2.0;
+const AGENT_RADIUS: f32 = 0.6;
+/// Height of steps units can walk up
+const STEP_HEIGHT: f32 = 0.5;
+/// Seconds to wait after the last level collider was added before baking
+const BAKE_DELAY: f32 = 0.5;
+/// A* gives up after expanding this many nodes
+const MAX_SEARCH_NODES: usize = 50_000;
+const REPATH_INTERVAL: f32 = 1.0;
+/// Path is recomputed soon when the target moves further than this
+const REPATH_DISTANCE: f32 = 5.0;
+/// A* searches per frame. Agents that are due wait for later frames, longest waiting first.
+const MAX_REPATHS_PER_FRAME: usize = 6;
+
+pub struct NavMeshPlugin;
+impl Plugin for NavMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NavMesh>().add_systems(
+            Update,
+            (bake_navmesh, update_nav_paths)
+                .chain()
+                .run_if(in_state(GameLoading::Loaded)),
+        );
+    }
+}
+
+/// Walkable spot in the middle of a cell. Columns can have several nodes stacked on top of each other.
+#[derive(Clone, Debug)]
+pub struct NavNode {
+    pub pos: Vec3,
+    pub cell: IVec2,
+    /// Linked nodes in the +x, -x, +z, -z cells
+    pub neighbors: [Option<u32>; 4],
+}
+
+const NEIGHBOR_DIRS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+/// Grid of walkable cells sampled from the level colliders added by `setup_trimesh_colliders`.
+/// Each cell is a square polygon, linked cells share an edge that is used as a portal when smoothing paths.
+#[derive(Resource, Default)]
+pub struct NavMesh {
+    pub cell_size: f32,
+    /// World space xz of the corner of cell (0, 0)
+    pub origin: Vec2,
+    pub size: IVec2,
+    /// Node index range of each column
+    columns: Vec<(u32, u32)>,
+    pub nodes: Vec<NavNode>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct OpenNode {
+    cost: f32,
+    node: u32,
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed for a min heap
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl NavMesh {
+    pub fn is_baked(&self) -> bool {
+        !self.nodes.is_empty()
+    }
+
+    fn cell_of(&self, pos: Vec3) -> IVec2 {
+        ((pos.xz() - self.origin) / self.cell_size)
+            .floor()
+            .as_ivec2()
+    }
+
+    fn cell_min(&self, cell: IVec2) -> Vec2 {
+        self.origin + cell.as_vec2() * self.cell_size
+    }
+
+    fn column(&self, cell: IVec2) -> &[NavNode] {
+        if cell.cmplt(IVec2::ZERO).any() || cell.cmpge(self.size).any() {
+            return &[];
+        }
+        let (start, end) = self.columns[(cell.y * self.size.x + cell.x) as usize];
+        &self.nodes[start as usize..end as usize]
+    }
+
+    fn column_start(&self, cell: IVec2) -> u32 {
+        self.columns[(cell.y * self.size.x + cell.x) as usize].0
+    }
+
+    /// Node in the cell under `pos`, preferring the highest one that isn't above it.
+    /// Looks in the surrounding cells if the cell under `pos` isn't walkable.
+    pub fn nearest_node(&self, pos: Vec3) -> Option<u32> {
+        if !self.is_baked() {
+            return None;
+        }
+        let center = self.cell_of(pos);
+        for ring in 0..4 {
+            let mut best: Option<(u32, f32)> = None;
+            for z in -ring..=ring {
+                for x in -ring..=ring {
+                    if x.abs() != ring && z.abs() != ring {
+                        continue;
+                    }
+                    let cell = center + IVec2::new(x, z);
+                    for (i, node) in self.column(cell).iter().enumerate() {
+                        let dy = pos.y + STEP_HEIGHT - node.pos.y;
+                        // Nodes above pos are a lot worse, they are probably a floor above
+                        let dy_score = if dy >= 0.0 { dy } else { -dy * 10.0 };
+                        let score = dy_score + node.pos.xz().distance(pos.xz());
+                        if best.map_or(true, |(_, best_score)| score < best_score) {
+                            best = Some((self.column_start(cell) + i as u32, score));
+                        }
+                    }
+                }
+            }
+            if let Some((node, _)) = best {
+                return Some(node);
+            }
+        }
+        None
+    }
+
+    /// Ground height under `pos`, interpolated towards the neighboring cells
+    pub fn ground_height(&self, pos: Vec3) -> Option<f32> {
+        let node = &self.nodes[self.nearest_node(pos)? as usize];
+        if node.cell != self.cell_of(pos) {
+            return None;
+        }
+        let offset = (pos.xz() - node.pos.xz()) / self.cell_size;
+        let neighbor_y =
+            |dir: usize| node.neighbors[dir].map_or(node.pos.y, |n| self.nodes[n as usize].pos.y);
+        let x_y = neighbor_y(if offset.x >= 0.0 { 0 } else { 1 });
+        let z_y = neighbor_y(if offset.y >= 0.0 { 2 } else { 3 });
+        Some(node.pos.y + offset.x.abs() * (x_y - node.pos.y) + offset.y.abs() * (z_y - node.pos.y))
+    }
+
+    /// Smoothed path from `start` to `end`, both included. None if there is no path.
+    pub fn find_path(&self, start: Vec3, end: Vec3) -> Option<Vec<Vec3>> {
+        let start_node = self.nearest_node(start)?;
+        let end_node = self.nearest_node(end)?;
+        let corridor = self.astar(start_node, end_node)?;
+        Some(self.funnel(start, end, &corridor))
+    }
+
+    fn astar(&self, start: u32, end: u32) -> Option<Vec<u32>> {
+        let end_pos = self.nodes[end as usize].pos;
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<u32, u32> = HashMap::new();
+        let mut cost_so_far: HashMap<u32, f32> = HashMap::new();
+        open.push(OpenNode {
+            cost: 0.0,
+            node: start,
+        });
+        cost_so_far.insert(start, 0.0);
+        let mut expanded = 0;
+        while let Some(OpenNode { node, .. }) = open.pop() {
+            if node == end {
+                let mut corridor = vec![end];
+                let mut current = end;
+                while let Some(prev) = came_from.get(&current) {
+                    corridor.push(*prev);
+                    current = *prev;
+                }
+                corridor.reverse();
+                return Some(corridor);
+            }
+            expanded += 1;
+            if expanded > MAX_SEARCH_NODES {
+                return None;
+            }
+            let current = &self.nodes[node as usize];
+            let current_cost = cost_so_far[&node];
+            for neighbor in current.neighbors.iter().flatten() {
+                let neighbor_pos = self.nodes[*neighbor as usize].pos;
+                let cost = current_cost + current.pos.distance(neighbor_pos);
+                if cost_so_far.get(neighbor).map_or(true, |old| cost < *old) {
+                    cost_so_far.insert(*neighbor, cost);
+                    came_from.insert(*neighbor, node);
+                    open.push(OpenNode {
+                        cost: cost + neighbor_pos.distance(end_pos),
+                        node: *neighbor,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Shared edge of two linked cells, as (left, right) when walking from `a` to `b`
+    fn portal(&self, a: u32, b: u32) -> (Vec3, Vec3) {
+        let a = &self.nodes[a as usize];
+        let b = &self.nodes[b as usize];
+        let dir = b.cell - a.cell;
+        let min = self.cell_min(a.cell);
+        let cs = self.cell_size;
+        let (p0, p1) = match (dir.x, dir.y) {
+            (1, _) => (vec2(min.x + cs, min.y), vec2(min.x + cs, min.y + cs)),
+            (-1, _) => (vec2(min.x, min.y), vec2(min.x, min.y + cs)),
+            (_, 1) => (vec2(min.x, min.y + cs), vec2(min.x + cs, min.y + cs)),
+            _ => (vec2(min.x, min.y), vec2(min.x + cs, min.y)),
+        };
+        let y = (a.pos.y + b.pos.y) * 0.5;
+        let (p0, p1) = (vec3(p0.x, y, p0.y), vec3(p1.x, y, p1.y));
+        if triarea2(a.pos, p0, p1) < 0.0 {
+            (p1, p0)
+        } else {
+            (p0, p1)
+        }
+    }
+
+    // Simple stupid funnel algorithm
+    // https://digestingduck.blogspot.com/2010/03/simple-stupid-funnel-algorithm.html
+    fn funnel(&self, start: Vec3, end: Vec3, corridor: &[u32]) -> Vec<Vec3> {
+        let mut portals = vec![(start, start)];
+        for pair in corridor.windows(2) {
+            portals.push(self.portal(pair[0], pair[1]));
+        }
+        portals.push((end, end));
+
+        let mut path = vec![start];
+        let mut apex = start;
+        let (mut left, mut right) = portals[0];
+        let (mut apex_index, mut left_index, mut right_index) = (0, 0, 0);
+        let mut i = 1;
+        while i < portals.len() {
+            let (portal_left, portal_right) = portals[i];
+
+            if triarea2(apex, right, portal_right) <= 0.0 {
+                if apex == right || triarea2(apex, left, portal_right) > 0.0 {
+                    right = portal_right;
+                    right_index = i;
+                } else {
+                    // Right crossed over left, left becomes the new apex
+                    path.push(left);
+                    apex = left;
+                    apex_index = left_index;
+                    right = apex;
+                    right_index = apex_index;
+                    i = apex_index + 1;
+                    continue;
+                }
+            }
+
+            if triarea2(apex, left, portal_left) >= 0.0 {
+                if apex == left || triarea2(apex, right, portal_left) < 0.0 {
+                    left = portal_left;
+                    left_index = i;
+                } else {
+                    // Left crossed over right, right becomes the new apex
+                    path.push(right);
+                    apex = right;
+                    apex_index = right_index;
+                    left = apex;
+                    left_index = apex_index;
+                    i = apex_index + 1;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        if path.last() != Some(&end) {
+            path.push(end);
+        }
+        path
+    }
+}
+
+/// Twice the signed area of the triangle in the xz plane
+fn triarea2(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    let ab = b - a;
+    let ac = c - a;
+    ac.x * ab.z - ab.x * ac.z
+}
+
+/// Bake in progress, and the time the last level collider was added
+#[derive(Default)]
+pub struct NavMeshBaker {
+    dirty_since: Option<f32>,
+    task: Option<Task<NavMesh>>,
+}
+
+/// Level collision to bake against, copied out of the [`RapierContext`] so the bake can run off the main thread
+struct BakeInput {
+    context: RapierContext,
+    level_colliders: HashSet<Entity>,
+    min: Vec3,
+    max: Vec3,
+}
+
+/// Bakes the navmesh on the [`AsyncComputeTaskPool`] once the level colliders have settled, the old one is used
+/// until it's done. Bakes again if more level colliders are added.
+pub fn bake_navmesh(
+    mut navmesh: ResMut<NavMesh>,
+    mut baker: Local<NavMeshBaker>,
+    added_colliders: Query<(), Added<LevelCollider>>,
+    level_colliders: Query<(Entity, &GlobalTransform, &Aabb), With<LevelCollider>>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
+) {
+    if let Some(task) = &mut baker.task {
+        if let Some(baked) = block_on(future::poll_once(task)) {
+            *navmesh = baked;
+            baker.task = None;
+        }
+    }
+    let t = time.elapsed_seconds();
+    if !added_colliders.is_empty() {
+        baker.dirty_since = Some(t);
+    }
+    // Colliders added during a bake are picked up by the next one
+    if baker.task.is_some() {
+        return;
+    }
+    match baker.dirty_since {
+        Some(dirty_since) if t - dirty_since > BAKE_DELAY => baker.dirty_since = None,
+        _ => return,
+    }
+
+    let mut min = Vec3::INFINITY;
+    let mut max = Vec3::NEG_INFINITY;
+    for (_, global, aabb) in &level_colliders {
+        for corner in 0..8 {
+            let sign = Vec3::new(
+                if corner & 1 == 0 { -1.0 } else { 1.0 },
+                if corner & 2 == 0 { -1.0 } else { 1.0 },
+                if corner & 4 == 0 { -1.0 } else { 1.0 },
+            );
+            let p =
+                global.transform_point(Vec3::from(aabb.center + aabb.half_extents * sign.into()));
+            min = min.min(p);
+            max = max.max(p);
+        }
+    }
+    if !min.is_finite() || !max.is_finite() {
+        return;
+    }
+
+    // Shapes are shared, so this copies handles and positions rather than the level geometry
+    let mut context = RapierContext::default();
+    context.bodies = rapier_context.bodies.clone();
+    context.colliders = rapier_context.colliders.clone();
+    context.query_pipeline = rapier_context.query_pipeline.clone();
+    let input = BakeInput {
+        context,
+        level_colliders: level_colliders.iter().map(|(entity, ..)| entity).collect(),
+        min,
+        max,
+    };
+    baker.task = Some(AsyncComputeTaskPool::get().spawn(async move { bake(input) }));
+}
+
+fn bake(input: BakeInput) -> NavMesh {
+    let BakeInput {
+        context: rapier_context,
+        level_colliders,
+        min,
+        max,
+    } = input;
+    let start_time = Instant::now();
+    let extent = (max - min).xz();
+    let cell_size = NAV_CELL_SIZE.max((extent.x * extent.y / NAV_MAX_COLUMNS as f32).sqrt());
+    let size = (extent / cell_size).ceil().as_ivec2().max(IVec2::ONE);
+    let origin = min.xz();
+
+    let predicate = |entity| level_colliders.contains(&entity);
+    let filter = QueryFilter::default()
+        .exclude_sensors()
+        .predicate(&predicate);
+    let agent_shape = Collider::capsule_y(AGENT_HEIGHT * 0.5 - AGENT_RADIUS, AGENT_RADIUS);
+
+    // Sample walkable floors in each column, top to bottom
+    let mut columns = Vec::with_capacity((size.x * size.y) as usize);
+    let mut nodes = Vec::new();
+    let mut hits = Vec::new();
+    for z in 0..size.y {
+        for x in 0..size.x {
+            let cell = IVec2::new(x, z);
+            let center = origin + (cell.as_vec2() + 0.5) * cell_size;
+            hits.clear();
+            rapier_context.intersections_with_ray(
+                vec3(center.x, max.y + 1.0, center.y),
+                Vec3::NEG_Y,
+                max.y - min.y + 2.0,
+                false,
+                filter,
+                |_, intersection| {
+                    hits.push(intersection);
+                    true
+                },
+            );
+            hits.sort_by(|a, b| a.time_of_impact.total_cmp(&b.time_of_impact));
+            let start = nodes.len() as u32;
+            for (i, hit) in hits.iter().enumerate() {
+                if hit.normal.y < WALKABLE_NORMAL_Y {
+                    continue;
+                }
+                let floor = hit.point;
+                if i > 0 && hits[i - 1].point.y < floor.y + AGENT_HEIGHT {
+                    // Ceiling too low
+                    continue;
+                }
+                let agent_center = floor + Vec3::Y * (STEP_HEIGHT + AGENT_HEIGHT * 0.5);
+                if rapier_context
+                    .intersection_with_shape(agent_center, Quat::IDENTITY, &agent_shape, filter)
+                    .is_some()
+                {
+                    // Too close to a wall or pillar
+                    continue;
+                }
+                nodes.push(NavNode {
+                    pos: floor,
+                    cell,
+                    neighbors: [None; 4],
+                });
+            }
+            columns.push((start, nodes.len() as u32));
+        }
+    }
+
+    let mut baked = NavMesh {
+        cell_size,
+        origin,
+        size,
+        columns,
+        nodes,
+    };
+
+    // Link neighboring cells when the ground between them is continuous and nothing is in the way
+    let max_climb = cell_size + STEP_HEIGHT;
+    for i in 0..baked.nodes.len() {
+        let node = baked.nodes[i].clone();
+        for (dir_index, dir) in NEIGHBOR_DIRS.iter().enumerate() {
+            let other_cell = node.cell + *dir;
+            let other_start = if baked.column(other_cell).is_empty() {
+                continue;
+            } else {
+                baked.column_start(other_cell)
+            };
+            let link = baked
+                .column(other_cell)
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| (other.pos.y - node.pos.y).abs() <= max_climb)
+                .min_by(|(_, a), (_, b)| {
+                    (a.pos.y - node.pos.y)
+                        .abs()
+                        .total_cmp(&(b.pos.y - node.pos.y).abs())
+                })
+                .map(|(j, other)| (other_start + j as u32, other.pos));
+            let Some((other_index, other_pos)) = link else {
+                continue;
+            };
+            let from = node.pos + Vec3::Y * STEP_HEIGHT;
+            let to = other_pos + Vec3::Y * STEP_HEIGHT;
+            let blocked = rapier_context
+                .cast_ray(from, to - from, 1.0, true, filter)
+                .is_some();
+            if blocked {
+                continue;
+            }
+            // A cliff edge has the ground at the midpoint close to one side instead of in between
+            let mid = (node.pos + other_pos) * 0.5;
+            let mid_ground = rapier_context
+                .cast_ray(
+                    vec3(mid.x, node.pos.y.max(other_pos.y) + STEP_HEIGHT, mid.z),
+                    Vec3::NEG_Y,
+                    (node.pos.y - other_pos.y).abs() + STEP_HEIGHT * 2.0,
+                    true,
+                    filter,
+                )
+                .map(|(_, toi)| node.pos.y.max(other_pos.y) + STEP_HEIGHT - toi);
+            if mid_ground.map_or(true, |y| (y - mid.y).abs() > STEP_HEIGHT) {
+                continue;
+            }
+            baked.nodes[i].neighbors[dir_index] = Some(other_index);
+        }
+    }
+
+    info!(
+        "Baked navmesh with {} nodes over {}x{} cells in {:?}",
+        baked.nodes.len(),
+        size.x,
+        size.y,
+        start_time.elapsed()
+    );
+    baked
+}
+
+/// Path for a unit to follow to `target`. Set the target every frame, the path is recomputed
+/// every now and then and when the target moves too far, within a per frame budget.
+#[derive(Component, Clone, Default)]
+pub struct NavPath {
+    pub target: Vec3,
+    waypoints: Vec<Vec3>,
+    next_waypoint: usize,
+    /// Target the current waypoints lead to
+    path_target: Vec3,
+    next_repath: f32,
+    /// Time the agent started waiting for a repath
+    due_since: Option<f32>,
+}
+
+impl NavPath {
+    /// Point to steer towards, None if there is no path and the unit should head straight to the target.
+    /// Waypoints are skipped once the unit is within half a cell of them.
+    pub fn steer_point(&mut self, pos: Vec3, navmesh: &NavMesh) -> Option<Vec3> {
+        while self.next_waypoint + 1 < self.waypoints.len()
+            && self.waypoints[self.next_waypoint].xz().distance(pos.xz()) < navmesh.cell_size * 0.5
+        {
+            self.next_waypoint += 1;
+        }
+        self.waypoints.get(self.next_waypoint).copied()
+    }
+}
+
+pub fn update_nav_paths(
+    navmesh: Res<NavMesh>,
+    mut agents: Query<(Entity, &Transform, &mut NavPath)>,
+    time: Res<Time>,
+) {
+    if !navmesh.is_baked() {
+        return;
+    }
+    let t = time.elapsed_seconds();
+    let mut due = Vec::new();
+    for (entity, _, mut nav_path) in &mut agents {
+        let target_moved = nav_path.target.distance(nav_path.path_target) > REPATH_DISTANCE;
+        if t < nav_path.next_repath && !target_moved {
+            nav_path.due_since = None;
+            continue;
+        }
+        let since = *nav_path.due_since.get_or_insert(t);
+        due.push((since, entity));
+    }
+    // Agents that became due on the same frame are taken in entity order, which spreads them over frames
+    due.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    for (_, entity) in due.into_iter().take(MAX_REPATHS_PER_FRAME) {
+        let Ok((_, trans, mut nav_path)) = agents.get_mut(entity) else {
+            continue;
+        };
+        nav_path.due_since = None;
+        // Spread interval repaths over frames
+        let jitter = (entity.index() % 16) as f32 / 16.0;
+        nav_path.next_repath = t + REPATH_INTERVAL * (1.0 + jitter);
+        nav_path.path_target = nav_path.target;
+        let target = nav_path.target;
+        nav_path.waypoints = navmesh
+            .find_path(trans.translation, target)
+            .unwrap_or_default();
+        // Skip the start point
+        nav_path.next_waypoint = 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flat 1m grid from rows of `.` for walkable and `#` for blocked cells, rows going +z and columns +x
+    fn grid(rows: &[&str]) -> NavMesh {
+        let size = IVec2::new(rows[0].len() as i32, rows.len() as i32);
+        let walkable = |cell: IVec2| {
+            cell.cmpge(IVec2::ZERO).all()
+                && cell.cmplt(size).all()
+                && rows[cell.y as usize].as_bytes()[cell.x as usize] == b'.'
+        };
+        let mut navmesh = NavMesh {
+            cell_size: 1.0,
+            origin: Vec2::ZERO,
+            size,
+            ..default()
+        };
+        for z in 0..size.y {
+            for x in 0..size.x {
+                let cell = IVec2::new(x, z);
+                let start = navmesh.nodes.len() as u32;
+                if walkable(cell) {
+                    navmesh.nodes.push(NavNode {
+                        pos: vec3(x as f32 + 0.5, 0.0, z as f32 + 0.5),
+                        cell,
+                        neighbors: [None; 4],
+                    });
+                }
+                navmesh.columns.push((start, navmesh.nodes.len() as u32));
+            }
+        }
+        for i in 0..navmesh.nodes.len() {
+            let cell = navmesh.nodes[i].cell;
+            for (dir_index, dir) in NEIGHBOR_DIRS.iter().enumerate() {
+                if walkable(cell + *dir) {
+                    navmesh.nodes[i].neighbors[dir_index] = Some(navmesh.column_start(cell + *dir));
+                }
+            }
+        }
+        navmesh
+    }
+
+    fn node_at(navmesh: &NavMesh, x: i32, z: i32) -> u32 {
+        navmesh.column_start(IVec2::new(x, z))
+    }
+
+    #[test]
+    fn astar_goes_around_walls() {
+        let navmesh = grid(&["...", "##.", "..."]);
+        let start = node_at(&navmesh, 0, 0);
+        let end = node_at(&navmesh, 0, 2);
+        let corridor = navmesh.astar(start, end).unwrap();
+
+        let cells: Vec<IVec2> = corridor
+            .iter()
+            .map(|node| navmesh.nodes[*node as usize].cell)
+            .collect();
+        assert_eq!(
+            cells,
+            [(0, 0), (1, 0), (2, 0), (2, 1), (2, 2), (1, 2), (0, 2)].map(IVec2::from)
+        );
+        for pair in corridor.windows(2) {
+            assert!(navmesh.nodes[pair[0] as usize]
+                .neighbors
+                .contains(&Some(pair[1])));
+        }
+    }
+
+    #[test]
+    fn astar_to_itself() {
+        let navmesh = grid(&["..."]);
+        let node = node_at(&navmesh, 1, 0);
+        assert_eq!(navmesh.astar(node, node), Some(vec![node]));
+    }
+
+    #[test]
+    fn astar_fails_when_disconnected() {
+        let navmesh = grid(&[".#.", ".#."]);
+        let start = node_at(&navmesh, 0, 0);
+        let end = node_at(&navmesh, 2, 1);
+        assert_eq!(navmesh.astar(start, end), None);
+    }
+
+    #[test]
+    fn funnel_straight_corridor() {
+        let navmesh = grid(&["...."]);
+        let start = vec3(0.5, 0.0, 0.5);
+        let end = vec3(3.5, 0.0, 0.5);
+        let corridor = navmesh
+            .astar(node_at(&navmesh, 0, 0), node_at(&navmesh, 3, 0))
+            .unwrap();
+        assert_eq!(navmesh.funnel(start, end, &corridor), [start, end]);
+    }
+
+    #[test]
+    fn funnel_cuts_corners() {
+        let navmesh = grid(&["...", "##.", "##."]);
+        let start = vec3(0.5, 0.0, 0.5);
+        let end = vec3(2.5, 0.0, 2.5);
+        let corner = vec3(2.0, 0.0, 1.0);
+        let corridor = navmesh
+            .astar(node_at(&navmesh, 0, 0), node_at(&navmesh, 2, 2))
+            .unwrap();
+        assert_eq!(navmesh.funnel(start, end, &corridor), [start, corner, end]);
+
+        // Same corner the other way around
+        let corridor: Vec<u32> = corridor.into_iter().rev().collect();
+        assert_eq!(navmesh.funnel(end, start, &corridor), [end, corner, start]);
+    }
+
+    #[test]
+    fn find_path_from_world_positions() {
+        let navmesh = grid(&["...", "##.", "##."]);
+        let path = navmesh
+            .find_path(vec3(0.2, 0.0, 0.7), vec3(2.6, 0.0, 2.3))
+            .unwrap();
+        assert_eq!(
+            path,
+            [
+                vec3(0.2, 0.0, 0.7),
+                vec3(2.0, 0.0, 1.0),
+                vec3(2.6, 0.0, 2.3)
+            ]
+        );
+    }
+}
@@ -26,6 +26,20 @@ impl Plugin for PhysicsStuff {
 #[derive(Component, Clone, Copy)]
 pub struct AddTrimeshPhysics;
 
+/// Collision group of the player's body
+pub const PLAYER_GROUP: Group = Group::GROUP_2;
+/// Collision group of unit corpses. They don't touch the player, and shots and player movement look past them.
+pub const CORPSE_GROUP: Group = Group::GROUP_3;
+/// Scene query groups that skip corpses
+pub const SKIP_CORPSES: CollisionGroups = CollisionGroups {
+    memberships: Group::ALL,
+    filters: Group::ALL.difference(CORPSE_GROUP),
+};
+
+/// Trimesh collider of the level, added by [`setup_trimesh_colliders`]
+#[derive(Component, Clone, Copy)]
+pub struct LevelCollider;
+
 pub fn setup_trimesh_colliders(
     mut commands: Commands,
     scene_entities: Query<Entity, With<AddTrimeshPhysics>>,
@@ -41,6 +55,7 @@ pub fn setup_trimesh_colliders(
                     // TODO seems inefficient if there are multiple instances of the same trimesh collider
                     commands.entity(entity).insert((
                         Collider::from_bevy_mesh(mesh, &ComputedColliderShape::TriMesh).unwrap(),
+                        LevelCollider,
                         //RigidBody::Fixed, // Seemed to be moving some objects or something, but collision works without
                     ));
                 }
@@ -0,0 +1,462 @@
+use std::f32::consts::TAU;
+
+use bevy::{animation::ActiveAnimation, math::vec3, prelude::*};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    animation::{AnimNotifies, AnimStateMachine},
+    character_controller::Player,
+    damage::{DamageEvent, DamageKind, Damageable},
+    fps_controller::LogicalPlayer,
+    guns::weapon_def::TargetClass,
+    menu::menu_ui,
+    mesh_assets::{MeshAssets, StabbyClip},
+    waves::SpawnEnemyEvent,
+    GameLoading,
+};
+
+use super::{
+    enemy::{
+        face_dest, spawn_enemy, AttackContext, AttackStyle, EnemyArchetype, EnemyBehaviour,
+        EnemyDisabled, EnemyUnit, EnemyUnitPlugin,
+    },
+    enemy_projectile::{lob_velocity, spawn_enemy_projectile, EnemyProjectile},
+    hit_volumes::{HitVolumeDef, HitZone},
+    perception::{AggroState, Perception, PerceptionDef},
+    plum::Plum,
+    Explosion,
+};
+
+pub type BossUnitPlugin = EnemyUnitPlugin<Boss>;
+pub type BossUnit = EnemyUnit<Boss>;
+
+const BOSS_MELEE_RADIUS: f32 = 12.0;
+const BOSS_MELEE_DAMAGE: f32 = 25.0;
+/// Player is kept this far inside the arena bounds, in the sensor's local space where the bounds are -1..1
+const ARENA_LOCK_MARGIN: f32 = 0.05;
+const SPIT_SPEED: f32 = 30.0;
+const SPIT_GRAVITY: f32 = 6.0;
+const SPIT_RADIUS: f32 = 0.8;
+const SPIT_DAMAGE: f32 = 10.0;
+const SPIT_LIFETIME: f32 = 6.0;
+/// Where spit leaves the model, before SCALE
+const SPIT_ORIGIN: Vec3 = Vec3::new(0.0, 2.5, -1.5);
+/// Distance from the boss minions are spawned at
+const MINION_SPAWN_RADIUS: f32 = 8.0;
+
+/// Boss fight flow, the boss itself uses the regular enemy movement and melee attack
+pub struct BossPlugin;
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BossFight>().add_systems(
+            Update,
+            (
+                start_boss_fight.run_if(not(resource_exists::<EnemyDisabled<Boss>>)),
+                update_boss_fight,
+                boss_attacks,
+                lock_player_in_arena,
+            )
+                .chain()
+                .run_if(in_state(GameLoading::Loaded))
+                .before(menu_ui),
+        );
+    }
+}
+
+/// Added to level glTF nodes with BOSS_ARENA in their name. The node should also be a SENSOR, entering it starts the
+/// fight and the player can't leave it until the boss is dead.
+#[derive(Component, Clone)]
+pub struct BossArena;
+
+/// Added to level glTF nodes with BOSS_SPAWN in their name. The boss spawns in the middle of the arena without one.
+#[derive(Component, Clone)]
+pub struct BossSpawnPoint;
+
+#[derive(Clone, Copy, Debug)]
+pub enum BossAttack {
+    /// Fan of spit projectiles aimed at the player
+    SpitVolley { count: u32, spread: f32 },
+    /// Spiders spawned in a ring around the boss
+    SummonMinions { count: u32 },
+    /// Damages the player if they are within `radius`
+    Slam { radius: f32, damage: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BossPhase {
+    pub name: &'static str,
+    /// Phase starts once the health fraction drops below this
+    pub health_below: f32,
+    /// Used in order, repeating
+    pub attacks: &'static [BossAttack],
+    /// Seconds between attacks
+    pub attack_interval: f32,
+}
+
+/// Ordered by `health_below`, highest first
+pub const BOSS_PHASES: &[BossPhase] = &[
+    BossPhase {
+        name: "THE MOTHER",
+        health_below: 1.01,
+        attacks: &[BossAttack::SpitVolley {
+            count: 3,
+            spread: 0.25,
+        }],
+        attack_interval: 3.0,
+    },
+    BossPhase {
+        name: "THE MOTHER, ANGERED",
+        health_below: 0.66,
+        attacks: &[
+            BossAttack::SummonMinions { count: 6 },
+            BossAttack::SpitVolley {
+                count: 5,
+                spread: 0.2,
+            },
+            BossAttack::SpitVolley {
+                count: 5,
+                spread: 0.2,
+            },
+        ],
+        attack_interval: 2.5,
+    },
+    BossPhase {
+        name: "THE MOTHER, ENRAGED",
+        health_below: 0.33,
+        attacks: &[
+            BossAttack::Slam {
+                radius: 20.0,
+                damage: 20.0,
+            },
+            BossAttack::SpitVolley {
+                count: 9,
+                spread: 0.15,
+            },
+            BossAttack::SummonMinions { count: 10 },
+            BossAttack::SpitVolley {
+                count: 9,
+                spread: 0.15,
+            },
+        ],
+        attack_interval: 1.5,
+    },
+];
+
+/// State of the boss while alive
+#[derive(Component, Clone, Copy, Default)]
+pub struct BossState {
+    pub phase: usize,
+    next_attack: f32,
+    attack_index: usize,
+}
+
+impl BossState {
+    pub fn phase(&self) -> &'static BossPhase {
+        &BOSS_PHASES[self.phase]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BossFightState {
+    #[default]
+    Inactive,
+    /// Player is locked in the arena with the boss
+    Active {
+        arena: Entity,
+        boss: Entity,
+    },
+    Defeated,
+}
+
+#[derive(Resource, Default)]
+pub struct BossFight {
+    pub state: BossFightState,
+}
+
+impl BossFight {
+    pub fn active(&self) -> bool {
+        matches!(self.state, BossFightState::Active { .. })
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Boss;
+
+/// Uses the plum model, scaled up
+impl EnemyArchetype for Boss {
+    type Clip = StabbyClip;
+    const NAME: &'static str = "Boss";
+    const CLASS: TargetClass = TargetClass::Boss;
+    const SCALE: f32 = 3.0;
+    const HIT_VOLUMES: &'static [HitVolumeDef] = Plum::HIT_VOLUMES;
+    const DAMAGEABLE: Damageable = Damageable {
+        health: 3000.0,
+        resistances: &[(DamageKind::Explosion, 0.5)],
+        zone_multipliers: &[(HitZone::Head, 1.5), (HitZone::Legs, 0.5)],
+    };
+    const BEHAVIOUR: EnemyBehaviour = EnemyBehaviour {
+        attack_style: AttackStyle::OneShot,
+        attack_dist: BOSS_MELEE_RADIUS,
+        attack_cooldown: 2.0,
+        stop_dist: BOSS_MELEE_RADIUS * 0.5,
+    };
+    const CROWD_RADIUS: f32 = 4.0;
+    const PERCEPTION: PerceptionDef = PerceptionDef {
+        sight_dist: 300.0,
+        fov_cos: -1.0,
+        sense_dist: 300.0,
+        hearing_radius: 300.0,
+        search_time: 30.0,
+        eye_height: 2.6,
+    };
+
+    fn scene(mesh_assets: &MeshAssets) -> Handle<Scene> {
+        mesh_assets.plum.clone()
+    }
+
+    fn gltf(mesh_assets: &MeshAssets) -> Handle<Gltf> {
+        mesh_assets.plum_gltf.clone_weak()
+    }
+
+    fn anim_state_machine(mesh_assets: &MeshAssets) -> Handle<AnimStateMachine> {
+        mesh_assets.boss_anim.clone()
+    }
+
+    fn notifies(mesh_assets: &MeshAssets) -> Option<Handle<AnimNotifies>> {
+        Some(mesh_assets.plum_notifies.clone())
+    }
+
+    fn walk_step(anim: &ActiveAnimation, dt: f32) -> Option<(f32, f32)> {
+        let base_walk_speed = 4.0;
+        let anim_speed = anim.speed();
+        Some((Self::SCALE * base_walk_speed * anim_speed, dt * anim_speed))
+    }
+
+    fn turn_step(anim: &ActiveAnimation, dt: f32) -> f32 {
+        let base_turn_speed = 0.5;
+        dt * base_turn_speed * anim.speed() * TAU
+    }
+
+    fn attack(ctx: &mut AttackContext) {
+        if !ctx.notified("hit") {
+            face_dest(ctx.unit_trans, ctx.dest, 0.1 * ctx.anim.speed());
+            return;
+        }
+        if ctx.dest.distance(ctx.unit_trans.translation) < BOSS_MELEE_RADIUS {
+            ctx.damage_events.send(DamageEvent {
+                target: ctx.player_entity,
+                amount: BOSS_MELEE_DAMAGE,
+                kind: DamageKind::Melee,
+                hit_point: ctx.dest,
+                zone: None,
+                source: Some(ctx.unit_entity),
+            });
+        }
+    }
+
+    fn on_death(commands: &mut Commands, mesh_assets: &MeshAssets, trans: &Transform) {
+        for i in 0..5 {
+            let offset = Quat::from_rotation_y(i as f32 * TAU / 5.0) * Vec3::Z * 4.0;
+            commands.spawn((
+                SceneBundle {
+                    scene: mesh_assets.exp.clone(),
+                    transform: Transform::from_translation(trans.translation + offset)
+                        .with_scale(Vec3::splat(2.0)),
+                    ..default()
+                },
+                Explosion(0.0),
+            ));
+        }
+    }
+}
+
+fn start_boss_fight(
+    mut commands: Commands,
+    mut fight: ResMut<BossFight>,
+    mut collision_events: EventReader<CollisionEvent>,
+    arenas: Query<&GlobalTransform, With<BossArena>>,
+    spawn_points: Query<&GlobalTransform, With<BossSpawnPoint>>,
+    logical_player: Query<Entity, With<LogicalPlayer>>,
+    player: Query<(&Transform, &Player), With<Camera3d>>,
+    mesh_assets: Res<MeshAssets>,
+) {
+    let (Ok(logical_player), Ok((player_trans, player_stats))) =
+        (logical_player.get_single(), player.get_single())
+    else {
+        return;
+    };
+    for event in collision_events.read() {
+        let CollisionEvent::Started(a, b, _) = *event else {
+            continue;
+        };
+        let arena = if b == logical_player { a } else { b };
+        if fight.state != BossFightState::Inactive
+            || player_stats.health < 0.0
+            || (a != logical_player && b != logical_player)
+        {
+            continue;
+        }
+        let Ok(arena_trans) = arenas.get(arena) else {
+            continue;
+        };
+        let spawn_pos = spawn_points
+            .iter()
+            .next()
+            .map_or(arena_trans.translation(), |p| p.translation());
+        let boss = spawn_enemy::<Boss>(&mut commands, &mesh_assets, spawn_pos);
+        let mut perception = Perception::default();
+        perception.alert(player_trans.translation);
+        commands
+            .entity(boss)
+            .insert((BossState::default(), perception));
+        fight.state = BossFightState::Active { arena, boss };
+        info!("Boss fight started");
+    }
+}
+
+fn update_boss_fight(
+    mut fight: ResMut<BossFight>,
+    mut bosses: Query<(&Damageable, &mut BossState)>,
+    player: Query<&Player, With<Camera3d>>,
+) {
+    // Player stats are reset on restart
+    if player
+        .get_single()
+        .is_ok_and(|player| player.activity_start_time.is_none())
+    {
+        fight.state = BossFightState::Inactive;
+        return;
+    }
+    let BossFightState::Active { boss, .. } = fight.state else {
+        return;
+    };
+    let Ok((damageable, mut state)) = bosses.get_mut(boss) else {
+        fight.state = BossFightState::Defeated;
+        info!("Boss defeated");
+        return;
+    };
+    if damageable.dead() {
+        return;
+    }
+    let health = damageable.health / Boss::DAMAGEABLE.health;
+    let phase = BOSS_PHASES
+        .iter()
+        .rposition(|phase| health < phase.health_below)
+        .unwrap_or(0);
+    if phase != state.phase {
+        state.phase = phase;
+        state.attack_index = 0;
+        info!("Boss phase {}: {}", phase, state.phase().name);
+    }
+}
+
+fn boss_attacks(
+    mut commands: Commands,
+    mut bosses: Query<(Entity, &Transform, &Perception, &mut BossState)>,
+    player: Query<(Entity, &Transform, &Player), (With<Camera3d>, Without<BossState>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut spawn_events: EventWriter<SpawnEnemyEvent>,
+    mesh_assets: Res<MeshAssets>,
+    time: Res<Time>,
+) {
+    let Ok((player_entity, player_trans, player_stats)) = player.get_single() else {
+        return;
+    };
+    if player_stats.health < 0.0 {
+        return;
+    }
+    let t = time.elapsed_seconds();
+    for (boss_entity, boss_trans, perception, mut state) in &mut bosses {
+        // Phase attacks need line of sight, the regular melee still happens up close
+        if perception.state != AggroState::Chase || t < state.next_attack {
+            continue;
+        }
+        let phase = state.phase();
+        state.next_attack = t + phase.attack_interval;
+        let attack = phase.attacks[state.attack_index % phase.attacks.len()];
+        state.attack_index += 1;
+
+        match attack {
+            BossAttack::SpitVolley { count, spread } => {
+                let origin = boss_trans.transform_point(SPIT_ORIGIN);
+                let velocity =
+                    lob_velocity(origin, player_trans.translation, SPIT_SPEED, SPIT_GRAVITY);
+                for i in 0..count {
+                    let offset = (i as f32 - (count - 1) as f32 * 0.5) * spread;
+                    spawn_enemy_projectile(
+                        &mut commands,
+                        &mesh_assets,
+                        origin,
+                        EnemyProjectile {
+                            velocity: Quat::from_rotation_y(offset) * velocity,
+                            gravity: SPIT_GRAVITY,
+                            radius: SPIT_RADIUS,
+                            damage: SPIT_DAMAGE,
+                            spawned: t,
+                            lifetime: SPIT_LIFETIME,
+                            source: boss_entity,
+                        },
+                    );
+                }
+            }
+            BossAttack::SummonMinions { count } => {
+                for i in 0..count {
+                    let angle = i as f32 * TAU / count as f32;
+                    let offset = Quat::from_rotation_y(angle) * Vec3::Z * MINION_SPAWN_RADIUS;
+                    spawn_events.send(SpawnEnemyEvent {
+                        class: TargetClass::Spider,
+                        position: boss_trans.translation + offset,
+                    });
+                }
+            }
+            BossAttack::Slam { radius, damage } => {
+                commands.spawn((
+                    SceneBundle {
+                        scene: mesh_assets.exp.clone(),
+                        transform: Transform::from_translation(boss_trans.translation),
+                        ..default()
+                    },
+                    Explosion(0.0),
+                ));
+                let dist = player_trans.translation.distance(boss_trans.translation);
+                if dist < radius {
+                    damage_events.send(DamageEvent {
+                        target: player_entity,
+                        amount: damage * (1.0 - dist / radius),
+                        kind: DamageKind::Explosion,
+                        hit_point: player_trans.translation,
+                        zone: None,
+                        source: Some(boss_entity),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the player inside the arena sensor while the fight is on
+fn lock_player_in_arena(
+    fight: Res<BossFight>,
+    arenas: Query<&GlobalTransform, With<BossArena>>,
+    mut logical_player: Query<&mut Transform, With<LogicalPlayer>>,
+) {
+    let BossFightState::Active { arena, .. } = fight.state else {
+        return;
+    };
+    let (Ok(arena_trans), Ok(mut player_trans)) =
+        (arenas.get(arena), logical_player.get_single_mut())
+    else {
+        return;
+    };
+    let to_local = arena_trans.affine().inverse();
+    let local = to_local.transform_point3(player_trans.translation);
+    let limit = 1.0 - ARENA_LOCK_MARGIN;
+    let clamped = vec3(
+        local.x.clamp(-limit, limit),
+        local.y,
+        local.z.clamp(-limit, limit),
+    );
+    if clamped != local {
+        player_trans.translation = arena_trans.transform_point(clamped);
+    }
+}
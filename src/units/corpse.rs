@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    damage::LastHit,
+    menu::menu_ui,
+    physics::{CORPSE_GROUP, PLAYER_GROUP},
+    GameLoading,
+};
+
+/// Oldest corpses start sinking early once there are more than this
+const MAX_CORPSES: usize = 24;
+/// Seconds a corpse takes to sink out of sight
+const SINK_TIME: f32 = 2.0;
+/// Meters per second, in unscaled model space
+const SINK_SPEED: f32 = 1.5;
+const CORPSE_DENSITY: f32 = 2.0;
+
+pub struct CorpsePlugin;
+impl Plugin for CorpsePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            update_corpses
+                .run_if(in_state(GameLoading::Loaded))
+                .before(menu_ui),
+        );
+    }
+}
+
+/// Physics body an [`EnemyArchetype`](super::enemy::EnemyArchetype) leaves behind when it dies
+#[derive(Clone, Copy, Debug)]
+pub struct CorpseDef {
+    /// Box collider in unscaled model space
+    pub center: Vec3,
+    pub half_extents: Vec3,
+    /// Impulse per point of damage of the killing blow, applied at the hit point
+    pub impulse_per_damage: f32,
+    /// Seconds it lies around before sinking into the floor
+    pub lifetime: f32,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Corpse {
+    pub died: f32,
+    pub lifetime: f32,
+    /// Elapsed time it started sinking, physics is off from then on
+    sinking_since: Option<f32>,
+}
+
+/// Turns a unit root into a tumbling rigid body. The caller removes whatever made it a live unit.
+pub fn make_corpse(
+    commands: &mut Commands,
+    entity: Entity,
+    trans: &Transform,
+    def: &CorpseDef,
+    last_hit: Option<&LastHit>,
+    t: f32,
+) {
+    let impulse = last_hit.map_or(Vec3::ZERO, |hit| {
+        // A bit of lift so it doesn't just slide along the floor
+        (hit.direction + Vec3::Y * 0.3).normalize_or_zero() * hit.amount * def.impulse_per_damage
+    });
+    let center_of_mass = trans.transform_point(def.center);
+    let torque_impulse = last_hit.map_or(Vec3::ZERO, |hit| {
+        (hit.point - center_of_mass).cross(impulse)
+    });
+    commands.entity(entity).insert((
+        RigidBody::Dynamic,
+        // Rapier scales the collider with the transform
+        Collider::compound(vec![(
+            def.center,
+            Quat::IDENTITY,
+            Collider::cuboid(def.half_extents.x, def.half_extents.y, def.half_extents.z),
+        )]),
+        ColliderMassProperties::Density(CORPSE_DENSITY),
+        CollisionGroups::new(CORPSE_GROUP, Group::ALL.difference(PLAYER_GROUP)),
+        ExternalImpulse {
+            impulse,
+            torque_impulse,
+        },
+        Corpse {
+            died: t,
+            lifetime: def.lifetime,
+            sinking_since: None,
+        },
+    ));
+}
+
+fn update_corpses(
+    mut commands: Commands,
+    mut corpses: Query<(Entity, &mut Transform, &mut Corpse)>,
+    time: Res<Time>,
+) {
+    let t = time.elapsed_seconds();
+    let dt = time.delta_seconds();
+
+    let mut lying: Vec<(f32, Entity)> = corpses
+        .iter()
+        .filter(|(_, _, corpse)| corpse.sinking_since.is_none())
+        .map(|(entity, _, corpse)| (corpse.died, entity))
+        .collect();
+    lying.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let over_cap = lying.len().saturating_sub(MAX_CORPSES);
+    for (_, entity) in &lying[..over_cap] {
+        if let Ok((_, _, mut corpse)) = corpses.get_mut(*entity) {
+            corpse.lifetime = t - corpse.died;
+        }
+    }
+
+    for (entity, mut trans, mut corpse) in &mut corpses {
+        let Some(sinking_since) = corpse.sinking_since else {
+            if t >= corpse.died + corpse.lifetime {
+                corpse.sinking_since = Some(t);
+                commands.entity(entity).remove::<(
+                    RigidBody,
+                    Collider,
+                    ExternalImpulse,
+                    ColliderMassProperties,
+                )>();
+            }
+            continue;
+        };
+        if t > sinking_since + SINK_TIME {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        trans.translation.y -= SINK_SPEED * trans.scale.y * dt;
+    }
+}
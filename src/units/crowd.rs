@@ -0,0 +1,115 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::GameLoading;
+
+/// Larger agents and separation ranges search more rings of cells around them, see [`CrowdGrid::nearby`]
+const CROWD_CELL_SIZE: f32 = 4.0;
+/// Agents start steering away from each other at this multiple of their combined radius
+const SEPARATION_RANGE: f32 = 1.5;
+/// How strongly separation bends the walk direction
+const SEPARATION_WEIGHT: f32 = 1.2;
+/// Max meters per second overlapping agents are pushed apart
+const MAX_PUSH_SPEED: f32 = 4.0;
+
+pub struct CrowdPlugin;
+impl Plugin for CrowdPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CrowdGrid>().add_systems(
+            PreUpdate,
+            build_crowd_grid.run_if(in_state(GameLoading::Loaded)),
+        );
+    }
+}
+
+/// Unit that keeps its distance from other agents
+#[derive(Component, Clone, Copy)]
+pub struct CrowdAgent {
+    pub radius: f32,
+}
+
+/// Spatial hash of all [`CrowdAgent`]s in the xz plane, rebuilt at the start of every frame
+#[derive(Resource, Default)]
+pub struct CrowdGrid {
+    cells: HashMap<IVec2, Vec<(Entity, Vec3, f32)>>,
+    /// Largest radius of the agents in the grid
+    max_radius: f32,
+}
+
+impl CrowdGrid {
+    fn cell(pos: Vec3) -> IVec2 {
+        (pos.xz() / CROWD_CELL_SIZE).floor().as_ivec2()
+    }
+
+    /// Agents in the cells that overlap `range` meters around `pos`, excluding `entity`. Some can be further
+    /// than `range`.
+    pub fn nearby(
+        &self,
+        entity: Entity,
+        pos: Vec3,
+        range: f32,
+    ) -> impl Iterator<Item = (Entity, Vec3, f32)> + '_ {
+        let min = Self::cell(pos - Vec3::new(range, 0.0, range));
+        let max = Self::cell(pos + Vec3::new(range, 0.0, range));
+        (min.y..=max.y)
+            .flat_map(move |z| (min.x..=max.x).map(move |x| IVec2::new(x, z)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(move |(other, _, _)| *other != entity)
+    }
+
+    /// Returns (separation, push). Separation is a direction away from nearby agents weighted by how close they are,
+    /// push is the offset that would resolve overlaps.
+    pub fn separation(&self, entity: Entity, pos: Vec3, radius: f32) -> (Vec3, Vec3) {
+        let mut separation = Vec3::ZERO;
+        let mut push = Vec3::ZERO;
+        let max_range = (radius + self.max_radius) * SEPARATION_RANGE;
+        for (other, other_pos, other_radius) in self.nearby(entity, pos, max_range) {
+            let mut offset = pos - other_pos;
+            offset.y = 0.0;
+            let dist = offset.length();
+            let min_dist = radius + other_radius;
+            let range = min_dist * SEPARATION_RANGE;
+            if dist >= range {
+                continue;
+            }
+            // Units on the exact same spot pick a direction based on which one they are
+            let dir = if dist > 0.001 {
+                offset / dist
+            } else if entity.index() > other.index() {
+                Vec3::X
+            } else {
+                Vec3::NEG_X
+            };
+            separation += dir * (1.0 - dist / range);
+            if dist < min_dist {
+                push += dir * (min_dist - dist) * 0.5;
+            }
+        }
+        (separation, push)
+    }
+}
+
+/// Bends a normalized walk direction in the xz plane away from nearby agents
+pub fn steer_with_separation(dir: Vec3, separation: Vec3) -> Vec3 {
+    let mut steered = dir + separation * SEPARATION_WEIGHT;
+    steered.y = 0.0;
+    steered.normalize_or(dir)
+}
+
+/// Offset to apply this frame to resolve overlaps
+pub fn push_step(push: Vec3, dt: f32) -> Vec3 {
+    push.clamp_length_max(MAX_PUSH_SPEED * dt)
+}
+
+fn build_crowd_grid(mut grid: ResMut<CrowdGrid>, agents: Query<(Entity, &Transform, &CrowdAgent)>) {
+    grid.cells.clear();
+    grid.max_radius = 0.0;
+    for (entity, trans, agent) in &agents {
+        grid.max_radius = grid.max_radius.max(agent.radius);
+        grid.cells
+            .entry(CrowdGrid::cell(trans.translation))
+            .or_default()
+            .push((entity, trans.translation, agent.radius));
+    }
+}
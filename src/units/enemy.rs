@@ -0,0 +1,739 @@
+use std::{f32::consts::PI, marker::PhantomData};
+
+use bevy::{
+    animation::ActiveAnimation, gltf::GltfNode, math::vec3, prelude::*,
+    render::view::NoFrustumCulling,
+};
+
+use crate::{
+    animation::{
+        init_animation_graph, validate_anim_clips, AnimClips, AnimError, AnimLayerDef, AnimName,
+        AnimNotifies, AnimNotifyEvent, AnimParams, AnimPlayerController, AnimStateMachine,
+        AnimStateMachinePlayer, AnimationIndices, BlendSpaceDef, RootMotion,
+    },
+    character_controller::Player,
+    damage::{DamageEvent, Damageable, LastHit},
+    difficulty::Difficulty,
+    guns::weapon_def::TargetClass,
+    menu::menu_ui,
+    mesh_assets::MeshAssets,
+    navmesh::{NavMesh, NavPath},
+    units::crowd::{push_step, steer_with_separation, CrowdAgent, CrowdGrid},
+    util::{pfract, propagate, Propagate, PropagateDefault, FRAC_1_TAU},
+    waves::{SpawnEnemyEvent, WaveUnit},
+    GameLoading, ShaderCompSpawn, LEVEL_MAIN_FLOOR,
+};
+
+use super::{
+    corpse::{make_corpse, CorpseDef},
+    hit_volumes::{
+        find_hit_volume_bones, AddHitVolumes, HitVolume, HitVolumeBones, HitVolumeDef,
+        HitVolumeError,
+    },
+    perception::{update_perception, AggroState, Perception, PerceptionDef, ARRIVE_DIST},
+};
+
+crate::anim_names! {
+    /// Blend spaces [`EnemyArchetype::BLEND_SPACES`] can define
+    pub enum EnemyBlend {
+        /// Gets the `steer` parameter
+        Steer = "steer",
+    }
+}
+
+crate::anim_names! {
+    /// Layers [`EnemyArchetype::ANIM_LAYERS`] can define
+    pub enum EnemyLayer {
+        /// Played when the unit is hit, showing more of it for harder hits
+        Flinch = "flinch",
+    }
+}
+
+crate::anim_names! {
+    /// States every enemy state machine has
+    pub enum EnemyState {
+        Idle = "idle",
+        Attack = "attack",
+        Walk = "walk",
+        Wander = "wander",
+        TurnLeft = "turn_left",
+        TurnRight = "turn_right",
+    }
+}
+
+crate::anim_names! {
+    /// Parameters the enemy state machines can read
+    pub enum EnemyParam {
+        Idle = "idle",
+        Attack = "attack",
+        Walk = "walk",
+        TurnLeft = "turn_left",
+        TurnRight = "turn_right",
+        PlayerDead = "player_dead",
+        /// Difficulty speed multiplier
+        MoveSpeed = "move_speed",
+        /// -1 to 1, which way and how hard to turn while walking
+        Steer = "steer",
+    }
+}
+
+/// `steer` parameter per turn the destination is off to the side
+const STEER_GAIN: f32 = 8.0;
+/// Hits this hard or harder show the whole `flinch` layer
+const FLINCH_FULL_DAMAGE: f32 = 40.0;
+const FLINCH_ANIM_SPEED: f32 = 2.0;
+
+/// Everything that differs between enemy types. Implement this on a marker type and add
+/// [`EnemyUnitPlugin`] for it to get spawning, movement, attacking and death handling.
+pub trait EnemyArchetype: Clone + Default + Send + Sync + 'static {
+    /// Clips of the model, checked against the glTF when loading
+    type Clip: AnimName;
+    /// Used for debug UI
+    const NAME: &'static str;
+    const CLASS: TargetClass;
+    const SCALE: f32;
+    /// In unscaled model space, unit faces -Z
+    const HIT_VOLUMES: &'static [HitVolumeDef];
+    const DAMAGEABLE: Damageable;
+    const BEHAVIOUR: EnemyBehaviour;
+    /// World space meters other units keep from this unit's center
+    const CROWD_RADIUS: f32;
+    const PERCEPTION: PerceptionDef;
+    /// Physics body left lying around on death. None to despawn right away.
+    const CORPSE: Option<CorpseDef> = None;
+    /// The state machine can play these by name
+    const BLEND_SPACES: &'static [BlendSpaceDef<Self::Clip, EnemyBlend>] = &[];
+    const ANIM_LAYERS: &'static [AnimLayerDef<Self::Clip, EnemyLayer>] = &[];
+
+    fn scene(mesh_assets: &MeshAssets) -> Handle<Scene>;
+    /// Weak handle, used to look up the animation clips
+    fn gltf(mesh_assets: &MeshAssets) -> Handle<Gltf>;
+    /// Picks the clips. Gets the `idle`, `attack`, `turn_left`, `turn_right`, `walk` and `player_dead` flags, the
+    /// `move_speed` multiplier and `steer` from -1 (right) to 1 (left), and is expected to have `attack`, `turn_left`, `turn_right`, `walk` and `wander`
+    /// (walking once the player is dead) states.
+    fn anim_state_machine(mesh_assets: &MeshAssets) -> Handle<AnimStateMachine>;
+    /// Clip timeline events, attacks use them to time their hits
+    fn notifies(_mesh_assets: &MeshAssets) -> Option<Handle<AnimNotifies>> {
+        None
+    }
+
+    /// Rotation lerp factor towards the destination per frame while walking with root motion, before clip speed
+    const WALK_TURN_LERP: f32 = 0.15;
+
+    /// Movement while the walk clip plays, as (meters per second, rotation lerp factor towards the destination).
+    /// None to stand still this frame. Only used for clips without [`RootMotion`].
+    fn walk_step(_anim: &ActiveAnimation, _dt: f32) -> Option<(f32, f32)> {
+        None
+    }
+    /// Radians to turn this frame while a turn clip plays. Only used for clips without [`RootMotion`].
+    fn turn_step(_anim: &ActiveAnimation, _dt: f32) -> f32 {
+        0.0
+    }
+    /// Where the unit walks to while it sees the player. Melee units go straight for the player.
+    fn chase_dest(_unit_entity: Entity, _unit_pos: Vec3, player_pos: Vec3, _t: f32) -> Vec3 {
+        player_pos
+    }
+    /// Called every frame while the attack clip plays
+    fn attack(ctx: &mut AttackContext);
+    fn on_death(commands: &mut Commands, mesh_assets: &MeshAssets, trans: &Transform);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttackStyle {
+    /// Attack clip repeats while in range and facing the player, moving out of range stops it
+    Looping,
+    /// Attack clip plays once and can't be interrupted, the unit doesn't need to face the player to start it
+    OneShot,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EnemyBehaviour {
+    pub attack_style: AttackStyle,
+    /// Attacks start when the player is closer than this
+    pub attack_dist: f32,
+    /// Seconds from the start of one attack until the next can start
+    pub attack_cooldown: f32,
+    /// Unit stops walking once its destination is closer than this
+    pub stop_dist: f32,
+}
+
+/// What an [`EnemyArchetype::attack`] has access to
+pub struct AttackContext<'a, 'w, 's> {
+    pub commands: &'a mut Commands<'w, 's>,
+    pub damage_events: &'a mut EventWriter<'w, DamageEvent>,
+    pub mesh_assets: &'a MeshAssets,
+    pub unit_entity: Entity,
+    pub unit_trans: &'a mut Transform,
+    pub anim: ActiveAnimation,
+    pub player_entity: Entity,
+    /// Player position, or where units wander off to once the player is dead
+    pub dest: Vec3,
+    pub dt: f32,
+    /// Elapsed seconds
+    pub t: f32,
+    /// Attack clip notifies passed since the last frame
+    pub notifies: &'a [String],
+}
+
+impl AttackContext<'_, '_, '_> {
+    pub fn notified(&self, name: &str) -> bool {
+        self.notifies.iter().any(|notify| notify == name)
+    }
+}
+
+pub struct EnemyUnitPlugin<T: EnemyArchetype>(PhantomData<T>);
+
+impl<T: EnemyArchetype> Default for EnemyUnitPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: EnemyArchetype> Plugin for EnemyUnitPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                propagate::<EnemyUnitAnim<T>, AnimationPlayer>,
+                init_animation_graph::<EnemyUnitAnim<T>>,
+                put_self_on_parent::<T>,
+                enemy_spawner::<T>.run_if(not(resource_exists::<EnemyDisabled<T>>)),
+                update_perception::<T>,
+                move_to_player::<T>,
+                flinch_on_hit::<T>,
+                despawn_dead::<T>,
+            )
+                .chain()
+                .run_if(in_state(GameLoading::Loaded))
+                .before(menu_ui),
+        )
+        .add_systems(
+            OnExit(GameLoading::AssetLoading2),
+            (
+                validate_anim_clips::<EnemyUnitAnim<T>>
+                    .pipe(disable_enemy_on_error::<T, AnimError>),
+                find_unit_hit_volume_bones::<T>.pipe(disable_enemy_on_error::<T, HitVolumeError>),
+            ),
+        )
+        .add_systems(OnEnter(GameLoading::Loaded), shadercomp_enemy::<T>);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EnemyAction {
+    #[default]
+    Idle,
+    Attack,
+    Rotate,
+    Walk,
+}
+
+#[derive(Component, Clone, Default)]
+pub struct EnemyUnit<T: EnemyArchetype> {
+    pub action: EnemyAction,
+    /// Elapsed time the last attack started
+    last_attack: Option<f32>,
+    _archetype: PhantomData<T>,
+}
+
+#[derive(Component, Clone)]
+pub struct EnemyUnitAnim<T: EnemyArchetype> {
+    pub main_entity: Entity,
+    pub added_ref_to_self_on_parent: bool,
+    _archetype: PhantomData<T>,
+}
+
+impl<T: EnemyArchetype> EnemyUnitAnim<T> {
+    pub fn new(main_entity: Entity) -> Self {
+        Self {
+            main_entity,
+            added_ref_to_self_on_parent: false,
+            _archetype: PhantomData,
+        }
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct EnemyUnitAnimChildRef<T: EnemyArchetype>(pub Entity, PhantomData<T>);
+
+impl<T: EnemyArchetype> AnimClips for EnemyUnitAnim<T> {
+    type Clip = T::Clip;
+    type Blend = EnemyBlend;
+    type Layer = EnemyLayer;
+    type State = EnemyState;
+    type Param = EnemyParam;
+
+    fn get_gltf_id(mesh_assets: &MeshAssets) -> Handle<Gltf> {
+        T::gltf(mesh_assets)
+    }
+
+    fn get_notifies(mesh_assets: &MeshAssets) -> Option<Handle<AnimNotifies>> {
+        T::notifies(mesh_assets)
+    }
+
+    fn get_state_machine(mesh_assets: &MeshAssets) -> Option<Handle<AnimStateMachine>> {
+        Some(T::anim_state_machine(mesh_assets))
+    }
+
+    fn get_blend_spaces() -> &'static [BlendSpaceDef<T::Clip, EnemyBlend>] {
+        T::BLEND_SPACES
+    }
+
+    fn get_layers() -> &'static [AnimLayerDef<T::Clip, EnemyLayer>] {
+        T::ANIM_LAYERS
+    }
+}
+
+/// Inserted when the assets of `T` fail the checks on loading. Units of that type aren't spawned, the rest of the
+/// game still runs.
+#[derive(Resource)]
+pub struct EnemyDisabled<T: EnemyArchetype>(PhantomData<T>);
+
+fn disable_enemy_on_error<T: EnemyArchetype, E: std::fmt::Display>(
+    In(result): In<Result<(), E>>,
+    mut commands: Commands,
+) {
+    if let Err(err) = result {
+        error!("{} won't spawn: {}", T::NAME, err);
+        commands.insert_resource(EnemyDisabled::<T>(PhantomData));
+    }
+}
+
+/// Looks up the rest pose of the [`EnemyArchetype::HIT_VOLUMES`] bones
+fn find_unit_hit_volume_bones<T: EnemyArchetype>(
+    gltf_assets: Res<Assets<Gltf>>,
+    node_assets: Res<Assets<GltfNode>>,
+    mesh_assets: Res<MeshAssets>,
+    asset_server: Res<AssetServer>,
+    mut rest_poses: ResMut<HitVolumeBones>,
+) -> Result<(), HitVolumeError> {
+    let gltf_id = T::gltf(&mesh_assets).id();
+    // An unloaded glTF is reported by validate_anim_clips
+    let Some(gltf) = gltf_assets.get(gltf_id) else {
+        return Ok(());
+    };
+    let gltf_name = asset_server
+        .get_path(gltf_id)
+        .map_or_else(|| format!("{gltf_id:?}"), |path| path.to_string());
+    find_hit_volume_bones(
+        gltf_id,
+        &gltf_name,
+        gltf,
+        &node_assets,
+        T::HIT_VOLUMES,
+        &mut rest_poses,
+    )
+}
+
+pub fn spawn_enemy<T: EnemyArchetype>(
+    commands: &mut Commands,
+    mesh_assets: &MeshAssets,
+    translation: Vec3,
+) -> Entity {
+    let mut ecmds = commands.spawn((
+        SceneBundle {
+            scene: T::scene(mesh_assets),
+            transform: Transform::from_translation(translation).with_scale(Vec3::splat(T::SCALE)),
+            ..default()
+        },
+        EnemyUnit::<T>::default(),
+        T::DAMAGEABLE,
+        LastHit::default(),
+        NavPath::default(),
+        Perception::default(),
+        CrowdAgent {
+            radius: T::CROWD_RADIUS,
+        },
+        AddHitVolumes {
+            class: T::CLASS,
+            gltf: T::gltf(mesh_assets).id(),
+            volumes: T::HIT_VOLUMES,
+        },
+        NoFrustumCulling,
+        PropagateDefault(NoFrustumCulling),
+    ));
+    ecmds.insert(Propagate(EnemyUnitAnim::<T>::new(ecmds.id())));
+    ecmds.id()
+}
+
+/// Spawns the units the wave director asks for. They start out heading to where the player is.
+fn enemy_spawner<T: EnemyArchetype>(
+    mut commands: Commands,
+    mut spawn_events: EventReader<SpawnEnemyEvent>,
+    player: Query<&Transform, (With<Camera3d>, With<Player>)>,
+    mesh_assets: Res<MeshAssets>,
+) {
+    let player_pos = player.get_single().ok().map(|trans| trans.translation);
+    for event in spawn_events.read() {
+        if event.class == T::CLASS {
+            let entity = spawn_enemy::<T>(&mut commands, &mesh_assets, event.position);
+            let mut perception = Perception::default();
+            if let Some(player_pos) = player_pos {
+                perception.alert(player_pos);
+            }
+            commands.entity(entity).insert((WaveUnit, perception));
+        }
+    }
+}
+
+fn put_self_on_parent<T: EnemyArchetype>(
+    mut commands: Commands,
+    units: Query<Entity, With<EnemyUnit<T>>>,
+    mut unit_anims: Query<(Entity, &mut EnemyUnitAnim<T>)>,
+) {
+    for (unit_anim_entity, mut unit_anim) in &mut unit_anims {
+        if !unit_anim.added_ref_to_self_on_parent {
+            if let Ok(parent) = units.get(unit_anim.main_entity) {
+                commands
+                    .entity(parent)
+                    .insert(EnemyUnitAnimChildRef::<T>(unit_anim_entity, PhantomData));
+                unit_anim.added_ref_to_self_on_parent = true;
+            }
+        }
+    }
+}
+
+fn move_to_player<T: EnemyArchetype>(
+    mut commands: Commands,
+    mut units: Query<(
+        Entity,
+        &mut Transform,
+        &EnemyUnitAnimChildRef<T>,
+        &mut EnemyUnit<T>,
+        &mut NavPath,
+        &Perception,
+    )>,
+    time: Res<Time>,
+    mut unit_anims: Query<(
+        &mut AnimationTransitions,
+        &AnimationIndices,
+        &EnemyUnitAnim<T>,
+        &mut AnimationPlayer,
+        &mut AnimParams,
+        &mut AnimStateMachinePlayer,
+        Option<&RootMotion>,
+    )>,
+    player: Query<(Entity, &Transform, &Player), (With<Camera3d>, Without<EnemyUnit<T>>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mesh_assets: Res<MeshAssets>,
+    (navmesh, crowd, difficulty, clip_assets, mut notify_events): (
+        Res<NavMesh>,
+        Res<CrowdGrid>,
+        Res<Difficulty>,
+        Res<Assets<AnimationClip>>,
+        EventReader<AnimNotifyEvent>,
+    ),
+) {
+    let Ok((player_entity, player_trans, player_stats)) = player.get_single() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+    let t = time.elapsed_seconds();
+    let dead = player_stats.health < 0.0;
+    let behaviour = T::BEHAVIOUR;
+    let notify_events: Vec<AnimNotifyEvent> = notify_events.read().cloned().collect();
+
+    for (unit_entity, mut unit_trans, anim_child, mut unit, mut nav_path, perception) in &mut units
+    {
+        let Ok((
+            mut transitions,
+            anim,
+            _unit_anim,
+            mut player,
+            mut params,
+            mut state_machine,
+            root_motion,
+        )) = unit_anims.get_mut(anim_child.0)
+        else {
+            continue;
+        };
+        let mut player =
+            AnimPlayerController::new(&mut transitions, &mut player, anim, &mut params);
+        if state_machine.entered(EnemyState::Attack) {
+            unit.last_attack = Some(t);
+        }
+        state_machine.set_bool(EnemyParam::PlayerDead, dead);
+        state_machine.set(EnemyParam::MoveSpeed, difficulty.speed);
+        let attacking = state_machine.in_state(EnemyState::Attack);
+
+        // Overlapping units are pushed apart regardless of what they're doing
+        let (separation, push) =
+            crowd.separation(unit_entity, unit_trans.translation, T::CROWD_RADIUS);
+        unit_trans.translation += push_step(push, dt);
+
+        let chasing = !dead && perception.state == AggroState::Chase;
+        let dest = if dead {
+            Some(vec3(0.0, LEVEL_MAIN_FLOOR, -1200.0))
+        } else if chasing {
+            Some(T::chase_dest(
+                unit_entity,
+                unit_trans.translation,
+                player_trans.translation,
+                t,
+            ))
+        } else {
+            perception.last_known
+        };
+        // An attack that already started plays out at the player
+        let Some(dest) = dest.or(attacking.then_some(player_trans.translation)) else {
+            unit.action = EnemyAction::Idle;
+            hold_still(&mut state_machine);
+            continue;
+        };
+        let arrived =
+            !chasing && !dead && unit_trans.translation.xz().distance(dest.xz()) < ARRIVE_DIST;
+        if arrived && !attacking {
+            unit.action = EnemyAction::Idle;
+            hold_still(&mut state_machine);
+            continue;
+        }
+
+        // Follow the navmesh path, straight at dest if there isn't one
+        nav_path.target = dest;
+        let steer = nav_path
+            .steer_point(unit_trans.translation, &navmesh)
+            .unwrap_or(dest);
+
+        let forward = *unit_trans.forward();
+        let mut to_dest =
+            (steer - unit_trans.translation).normalize_or(unit_trans.translation + forward);
+        to_dest.y = forward.y;
+        to_dest = to_dest.normalize_or_zero();
+
+        let to_dist = (dest - unit_trans.translation).length();
+        // Chase destination isn't always the player, attacks are aimed at where the player is known to be
+        let target = if dead {
+            dest
+        } else {
+            perception.last_known.unwrap_or(player_trans.translation)
+        };
+        let target_dist = (target - unit_trans.translation).length();
+
+        let to_dest_dir = (to_dest.x.atan2(to_dest.z) + PI) * FRAC_1_TAU;
+        let forward_dir = (forward.x.atan2(forward.z) + PI) * FRAC_1_TAU;
+        let need_to_rotate_dir = pfract(forward_dir - to_dest_dir) - 0.5;
+
+        let need_to_turn = to_dest.dot(*unit_trans.forward()) < 0.93;
+
+        let buffer = 2.0;
+        let cooled_down = match unit.last_attack {
+            Some(last) => t > last + behaviour.attack_cooldown,
+            None => true,
+        };
+        let in_range = target_dist - buffer < behaviour.attack_dist && chasing && cooled_down;
+        let should_pursue = !need_to_turn && to_dist > behaviour.stop_dist;
+        let should_attack = match behaviour.attack_style {
+            AttackStyle::Looping => in_range && !need_to_turn,
+            AttackStyle::OneShot => in_range,
+        };
+        state_machine.set_bool(EnemyParam::Idle, false);
+        state_machine.set_bool(EnemyParam::Attack, should_attack);
+        state_machine.set_bool(
+            EnemyParam::TurnLeft,
+            need_to_turn && need_to_rotate_dir > 0.0,
+        );
+        state_machine.set_bool(
+            EnemyParam::TurnRight,
+            need_to_turn && need_to_rotate_dir <= 0.0,
+        );
+        state_machine.set_bool(EnemyParam::Walk, should_pursue);
+        state_machine.set(
+            EnemyParam::Steer,
+            (need_to_rotate_dir * STEER_GAIN).clamp(-1.0, 1.0),
+        );
+
+        // Clips follow the parameters once the state machine runs, this acts on what's playing now
+        let clip = state_machine.clip().to_string();
+        let Ok(Some(active_anim)) = player.animation(&clip) else {
+            continue;
+        };
+        if attacking {
+            unit.action = EnemyAction::Attack;
+            let attack_idx = anim.get(&clip).copied();
+            let notifies: Vec<String> = notify_events
+                .iter()
+                .filter(|event| event.entity == anim_child.0 && Some(event.clip) == attack_idx)
+                .map(|event| event.name.clone())
+                .collect();
+            T::attack(&mut AttackContext {
+                commands: &mut commands,
+                damage_events: &mut damage_events,
+                mesh_assets: &mesh_assets,
+                unit_entity,
+                unit_trans: &mut unit_trans,
+                anim: active_anim,
+                player_entity,
+                dest: target,
+                dt,
+                t,
+                notifies: &notifies,
+            });
+        } else if state_machine.in_state(EnemyState::Walk)
+            || state_machine.in_state(EnemyState::Wander)
+        {
+            unit.action = EnemyAction::Walk;
+            // Root motion sets the speed, the direction comes from steering
+            let step = match player.root_motion(&clip, dt, root_motion, &clip_assets) {
+                Some((delta, _)) => Some((
+                    delta.length() * T::SCALE / dt.max(f32::EPSILON),
+                    T::WALK_TURN_LERP * active_anim.speed(),
+                )),
+                None => T::walk_step(&active_anim, dt),
+            };
+            if let Some((speed, turn_lerp)) = step {
+                let current_y = unit_trans.translation.y;
+                unit_trans.translation += steer_with_separation(to_dest, separation) * dt * speed;
+                if let Some(ground_y) = navmesh.ground_height(unit_trans.translation) {
+                    unit_trans.translation.y = ground_y;
+                }
+                let dest_rot = unit_trans.looking_at(vec3(steer.x, current_y, steer.z), Vec3::Y);
+                unit_trans.rotation = unit_trans
+                    .rotation
+                    .lerp(dest_rot.rotation, turn_lerp.clamp(0.0, 1.0));
+            }
+        } else if state_machine.in_state(EnemyState::TurnLeft)
+            || state_machine.in_state(EnemyState::TurnRight)
+        {
+            unit.action = EnemyAction::Rotate;
+            let turn = match player.root_motion(&clip, dt, root_motion, &clip_assets) {
+                Some((_, yaw)) => yaw,
+                None => {
+                    let dir = if state_machine.in_state(EnemyState::TurnLeft) {
+                        1.0
+                    } else {
+                        -1.0
+                    };
+                    T::turn_step(&active_anim, dt) * dir
+                }
+            };
+            unit_trans.rotate_local_y(turn);
+        }
+    }
+}
+
+/// There's no idle clip, the state machine pauses the walk clip to stand still in the current pose
+fn hold_still(state_machine: &mut AnimStateMachinePlayer) {
+    state_machine.set_bool(EnemyParam::Idle, true);
+    for param in [
+        EnemyParam::Attack,
+        EnemyParam::TurnLeft,
+        EnemyParam::TurnRight,
+        EnemyParam::Walk,
+    ] {
+        state_machine.set_bool(param, false);
+    }
+}
+
+/// Rotates the unit towards `dest` while it attacks, `lerp` is clamped to 0..1
+pub fn face_dest(unit_trans: &mut Transform, dest: Vec3, lerp: f32) {
+    let dest_rot = unit_trans.looking_at(vec3(dest.x, unit_trans.translation.y, dest.z), Vec3::Y);
+    unit_trans.rotation = unit_trans
+        .rotation
+        .lerp(dest_rot.rotation, lerp.clamp(0.0, 1.0));
+}
+
+/// Plays the [`EnemyLayer::Flinch`] layer, for units that have one
+fn flinch_on_hit<T: EnemyArchetype>(
+    mut damage_events: EventReader<DamageEvent>,
+    units: Query<&EnemyUnitAnimChildRef<T>>,
+    mut unit_anims: Query<(
+        &mut AnimationTransitions,
+        &AnimationIndices,
+        &mut AnimationPlayer,
+        &mut AnimParams,
+    )>,
+) {
+    if !T::ANIM_LAYERS
+        .iter()
+        .any(|layer| layer.name == EnemyLayer::Flinch)
+    {
+        return;
+    }
+    for event in damage_events.read() {
+        let Ok(anim_child) = units.get(event.target) else {
+            continue;
+        };
+        let Ok((mut transitions, anim, mut player, mut params)) = unit_anims.get_mut(anim_child.0)
+        else {
+            continue;
+        };
+        let mut player =
+            AnimPlayerController::new(&mut transitions, &mut player, anim, &mut params);
+        let weight = (event.amount / FLINCH_FULL_DAMAGE).min(1.0);
+        if let Err(err) = player
+            .set_layer_weight(EnemyLayer::Flinch, weight)
+            .and_then(|_| player.play_layer(EnemyLayer::Flinch, FLINCH_ANIM_SPEED, false))
+        {
+            warn_once!("{}: {}", T::NAME, err);
+        }
+    }
+}
+
+fn despawn_dead<T: EnemyArchetype>(
+    mut commands: Commands,
+    units: Query<
+        (
+            Entity,
+            &Transform,
+            &Damageable,
+            &LastHit,
+            Option<&EnemyUnitAnimChildRef<T>>,
+        ),
+        With<EnemyUnit<T>>,
+    >,
+    mut anim_players: Query<&mut AnimationPlayer>,
+    hit_volumes: Query<(Entity, &HitVolume)>,
+    mesh_assets: Res<MeshAssets>,
+    time: Res<Time>,
+) {
+    for (entity, trans, damageable, last_hit, anim_ref) in &units {
+        if !damageable.dead() {
+            continue;
+        }
+        T::on_death(&mut commands, &mesh_assets, trans);
+        let Some(def) = T::CORPSE else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        // Strip everything that makes it a live unit, the scene stays as the corpse
+        commands.entity(entity).remove::<(
+            EnemyUnit<T>,
+            EnemyUnitAnimChildRef<T>,
+            Damageable,
+            LastHit,
+            NavPath,
+            Perception,
+            CrowdAgent,
+            WaveUnit,
+        )>();
+        for (volume_entity, volume) in &hit_volumes {
+            if volume.unit == entity {
+                commands.entity(volume_entity).despawn_recursive();
+            }
+        }
+        if let Some(mut anim_player) = anim_ref.and_then(|r| anim_players.get_mut(r.0).ok()) {
+            anim_player.pause_all();
+        }
+        make_corpse(
+            &mut commands,
+            entity,
+            trans,
+            &def,
+            Some(last_hit),
+            time.elapsed_seconds(),
+        );
+    }
+}
+
+fn shadercomp_enemy<T: EnemyArchetype>(mut commands: Commands, mesh_assets: Res<MeshAssets>) {
+    commands.spawn((
+        SceneBundle {
+            scene: T::scene(&mesh_assets),
+            transform: Transform::from_xyz(0.0, -5000.0, 0.0),
+            ..default()
+        },
+        NoFrustumCulling,
+        PropagateDefault(NoFrustumCulling),
+        ShaderCompSpawn,
+    ));
+}
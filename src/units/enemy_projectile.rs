@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    character_controller::Player,
+    damage::{DamageEvent, DamageKind},
+    mesh_assets::MeshAssets,
+    physics::LevelCollider,
+    GameLoading,
+};
+
+use super::Explosion;
+
+/// Player is treated as a capsule this far below the camera
+const PLAYER_HEIGHT: f32 = 1.6;
+const PLAYER_RADIUS: f32 = 0.5;
+
+pub struct EnemyProjectilePlugin;
+impl Plugin for EnemyProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            update_enemy_projectiles.run_if(in_state(GameLoading::Loaded)),
+        );
+    }
+}
+
+/// Slow shot fired by an enemy at the player. Meant to be dodged, so it's visible and travels in an arc.
+#[derive(Component, Clone, Copy)]
+pub struct EnemyProjectile {
+    pub velocity: Vec3,
+    /// Downwards acceleration in meters per second squared
+    pub gravity: f32,
+    pub radius: f32,
+    pub damage: f32,
+    pub spawned: f32,
+    /// Seconds until it pops on its own
+    pub lifetime: f32,
+    pub source: Entity,
+}
+
+/// Velocity that reaches `target` from `origin` at `speed` (measured horizontally) with `gravity`
+pub fn lob_velocity(origin: Vec3, target: Vec3, speed: f32, gravity: f32) -> Vec3 {
+    let offset = target - origin;
+    let flat = Vec3::new(offset.x, 0.0, offset.z);
+    let flight_time = flat.length() / speed.max(0.001);
+    let mut velocity = flat.normalize_or_zero() * speed;
+    velocity.y = offset.y / flight_time.max(0.001) + 0.5 * gravity * flight_time;
+    velocity
+}
+
+pub fn spawn_enemy_projectile(
+    commands: &mut Commands,
+    mesh_assets: &MeshAssets,
+    origin: Vec3,
+    projectile: EnemyProjectile,
+) {
+    commands.spawn((
+        SceneBundle {
+            scene: mesh_assets.exp.clone(),
+            transform: Transform::from_translation(origin)
+                .with_scale(Vec3::splat(projectile.radius)),
+            ..default()
+        },
+        projectile,
+    ));
+}
+
+fn update_enemy_projectiles(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &mut Transform, &mut EnemyProjectile)>,
+    player: Query<(Entity, &Transform, &Player), (With<Camera3d>, Without<EnemyProjectile>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    level_filter: Query<(), With<LevelCollider>>,
+    rapier_context: Res<RapierContext>,
+    mesh_assets: Res<MeshAssets>,
+    time: Res<Time>,
+) {
+    let player = player.get_single().ok();
+    let t = time.elapsed_seconds();
+    let dt = time.delta_seconds();
+    let predicate = |entity| level_filter.contains(entity);
+    let filter = QueryFilter::default()
+        .exclude_sensors()
+        .predicate(&predicate);
+
+    for (entity, mut trans, mut projectile) in &mut projectiles {
+        let start = trans.translation;
+        let step = projectile.velocity * dt;
+        let step_len = step.length();
+        let wall_toi = (step_len > 0.0)
+            .then(|| {
+                rapier_context.cast_ray(
+                    start,
+                    step / step_len,
+                    step_len + projectile.radius,
+                    true,
+                    filter,
+                )
+            })
+            .flatten()
+            .map(|(_, toi)| toi);
+        let end = start + step.normalize_or_zero() * wall_toi.unwrap_or(step_len);
+
+        // Closest approach between the projectile path and the player capsule
+        let hit_player = player.and_then(|(player_entity, player_trans, _)| {
+            let head = player_trans.translation;
+            let feet = head - Vec3::Y * PLAYER_HEIGHT;
+            let (a, b) = closest_points_on_segments(start, end, feet, head);
+            (a.distance(b) < projectile.radius + PLAYER_RADIUS).then_some((player_entity, a))
+        });
+
+        let expired = t > projectile.spawned + projectile.lifetime;
+        let impact = match (hit_player, wall_toi) {
+            (Some((_, point)), _) => Some(point),
+            (None, Some(_)) => Some(end),
+            (None, None) => expired.then_some(start),
+        };
+        let Some(impact) = impact else {
+            trans.translation = end;
+            projectile.velocity.y -= projectile.gravity * dt;
+            continue;
+        };
+
+        if let Some((player_entity, point)) = hit_player {
+            damage_events.send(DamageEvent {
+                target: player_entity,
+                amount: projectile.damage,
+                kind: DamageKind::Spit,
+                hit_point: point,
+                zone: None,
+                source: Some(projectile.source),
+            });
+        }
+        commands.entity(entity).despawn_recursive();
+        commands.spawn((
+            SceneBundle {
+                scene: mesh_assets.exp.clone(),
+                transform: Transform::from_translation(impact)
+                    .with_scale(Vec3::splat(projectile.radius)),
+                ..default()
+            },
+            // Starts part way in so the splash is smaller than a full explosion
+            Explosion(0.7),
+        ));
+    }
+}
+
+/// Closest points between segments p0-p1 and q0-q1
+fn closest_points_on_segments(p0: Vec3, p1: Vec3, q0: Vec3, q1: Vec3) -> (Vec3, Vec3) {
+    let d1 = p1 - p0;
+    let d2 = q1 - q0;
+    let r = p0 - q0;
+    let a = d1.length_squared();
+    let e = d2.length_squared();
+    let f = d2.dot(r);
+    let (s, t) = if a <= f32::EPSILON {
+        (0.0, (f / e.max(f32::EPSILON)).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(r);
+        let b = d1.dot(d2);
+        let denom = a * e - b * b;
+        let mut s = if denom > f32::EPSILON {
+            ((b * f - c * e) / denom).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let mut t = (b * s + f) / e.max(f32::EPSILON);
+        if t < 0.0 {
+            t = 0.0;
+            s = (-c / a).clamp(0.0, 1.0);
+        } else if t > 1.0 {
+            t = 1.0;
+            s = ((b - c) / a).clamp(0.0, 1.0);
+        }
+        (s, t)
+    };
+    (p0 + d1 * s, q0 + d2 * t)
+}
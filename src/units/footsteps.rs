@@ -0,0 +1,170 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use kira::tween::Tween;
+
+use crate::{
+    animation::AnimNotifyEvent,
+    audio::AudioAssets,
+    fps_controller::RenderPlayer,
+    menu::menu_ui,
+    minimal_kira_audio::{sound_data, KiraAudioManager, KiraSoundData, KiraTrackHandle},
+    GameLoading, SfxTrack,
+};
+
+/// Footsteps further than this from the player don't kick up dust
+const DUST_MAX_DISTANCE: f32 = 60.0;
+/// Seconds a puff of dust lasts
+const DUST_LIFETIME: f32 = 0.5;
+/// Meters across at its largest, in unscaled model space
+const DUST_SIZE: f32 = 0.8;
+/// Meters per second the puff rises
+const DUST_RISE_SPEED: f32 = 0.6;
+/// Footsteps further than this from the player can't be heard
+const STEP_SOUND_MAX_DISTANCE: f32 = 40.0;
+/// Volume right next to the player, falls off with distance
+const STEP_SOUND_VOLUME: f32 = 2.0;
+
+pub struct FootstepPlugin;
+impl Plugin for FootstepPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FootstepDust>().add_systems(
+            Update,
+            (
+                spawn_footstep_dust,
+                update_footstep_dust,
+                play_footstep_sounds,
+            )
+                .run_if(in_state(GameLoading::Loaded))
+                .before(menu_ui),
+        );
+    }
+}
+
+/// Mesh and material shared by all puffs of dust
+#[derive(Resource)]
+pub struct FootstepDust {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for FootstepDust {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Sphere::new(0.5).mesh().ico(1).unwrap());
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::srgba(0.45, 0.4, 0.35, 0.35),
+                alpha_mode: AlphaMode::Blend,
+                perceptual_roughness: 1.0,
+                ..default()
+            });
+        Self { mesh, material }
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+struct DustPuff {
+    spawned: f32,
+    size: f32,
+}
+
+/// Kicks up a puff of dust under the unit at each `footstep` notify
+fn spawn_footstep_dust(
+    mut commands: Commands,
+    mut notify_events: EventReader<AnimNotifyEvent>,
+    unit_anim_players: Query<&GlobalTransform>,
+    camera: Query<&GlobalTransform, With<RenderPlayer>>,
+    dust: Res<FootstepDust>,
+    time: Res<Time>,
+) {
+    let camera_pos = camera.get_single().ok().map(|trans| trans.translation());
+    for event in notify_events.read() {
+        if event.name != "footstep" {
+            continue;
+        }
+        let Ok(trans) = unit_anim_players.get(event.entity) else {
+            continue;
+        };
+        let (scale, _, pos) = trans.to_scale_rotation_translation();
+        if camera_pos.is_some_and(|camera_pos| camera_pos.distance(pos) > DUST_MAX_DISTANCE) {
+            continue;
+        }
+        commands.spawn((
+            PbrBundle {
+                mesh: dust.mesh.clone(),
+                material: dust.material.clone(),
+                transform: Transform::from_translation(pos).with_scale(Vec3::ZERO),
+                ..default()
+            },
+            DustPuff {
+                spawned: time.elapsed_seconds(),
+                size: DUST_SIZE * scale.x,
+            },
+        ));
+    }
+}
+
+/// Plays a step at each `footstep` notify, quieter and panned with distance like the spatial emitters
+fn play_footstep_sounds(
+    mut notify_events: EventReader<AnimNotifyEvent>,
+    unit_anim_players: Query<&GlobalTransform>,
+    camera: Query<&GlobalTransform, With<RenderPlayer>>,
+    audio_stuff: (
+        Option<Res<SfxTrack>>,
+        Res<Assets<KiraSoundData>>,
+        Res<Assets<KiraTrackHandle>>,
+        ResMut<KiraAudioManager>,
+        Res<AudioAssets>,
+    ),
+) {
+    let (sfx, sounds, tracks, mut manager, audio_assets) = audio_stuff;
+    let (Ok(camera), Some(track)) = (
+        camera.get_single(),
+        sfx.as_ref().and_then(|sfx| tracks.get(&sfx.handle)),
+    ) else {
+        notify_events.clear();
+        return;
+    };
+    for event in notify_events.read() {
+        if event.name != "footstep" {
+            continue;
+        }
+        let Ok(trans) = unit_anim_players.get(event.entity) else {
+            continue;
+        };
+        let to_step = trans.translation() - camera.translation();
+        let distance = to_step.length();
+        if distance > STEP_SOUND_MAX_DISTANCE {
+            continue;
+        }
+        let volume = STEP_SOUND_VOLUME / (1.0 + distance.max(1.0));
+        let panning = camera.right().dot(to_step.normalize_or_zero());
+        let mut sound = manager
+            .play(sound_data(&sounds, &audio_assets.footstep).output_destination(&track.0))
+            .unwrap();
+        sound.set_volume(volume as f64, Tween::default());
+        sound.set_panning((panning * 0.5 + 0.5) as f64, Tween::default());
+    }
+}
+
+/// Puffs swell, rise and shrink away
+fn update_footstep_dust(
+    mut commands: Commands,
+    mut puffs: Query<(Entity, &mut Transform, &DustPuff)>,
+    time: Res<Time>,
+) {
+    let t = time.elapsed_seconds();
+    let dt = time.delta_seconds();
+    for (entity, mut trans, puff) in &mut puffs {
+        let age = (t - puff.spawned) / DUST_LIFETIME;
+        if age >= 1.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        trans.translation.y += DUST_RISE_SPEED * dt;
+        trans.scale = Vec3::new(1.0, 0.5, 1.0) * puff.size * (age * PI).sin();
+    }
+}
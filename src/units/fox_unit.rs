@@ -4,9 +4,11 @@ use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 
 use crate::{
-    animation::{init_animation_graph, AnimClips, AnimationIndices},
+    animation::{
+        init_animation_graph, validate_anim_clips, AnimClips, AnimationIndices, NoAnimNames,
+    },
     menu::menu_ui,
-    mesh_assets::MeshAssets,
+    mesh_assets::{FoxClip, MeshAssets},
     util::{propagate, Propagate},
     GameLoading,
 };
@@ -24,6 +26,10 @@ impl Plugin for FoxUnitPlugin {
                 .chain()
                 .run_if(in_state(GameLoading::Loaded))
                 .before(menu_ui),
+        )
+        .add_systems(
+            OnExit(GameLoading::AssetLoading2),
+            validate_anim_clips::<FoxUnit>.map(bevy::utils::error),
         );
     }
 }
@@ -32,7 +38,13 @@ impl Plugin for FoxUnitPlugin {
 struct FoxUnit;
 
 impl AnimClips for FoxUnit {
-    fn get_gltf_id(&self, mesh_assets: &MeshAssets) -> Handle<Gltf> {
+    type Clip = FoxClip;
+    type Blend = NoAnimNames;
+    type Layer = NoAnimNames;
+    type State = NoAnimNames;
+    type Param = NoAnimNames;
+
+    fn get_gltf_id(mesh_assets: &MeshAssets) -> Handle<Gltf> {
         mesh_assets.fox_gltf.clone_weak()
     }
 }
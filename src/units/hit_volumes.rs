@@ -0,0 +1,375 @@
+use std::time::Duration;
+
+use bevy::{
+    gltf::GltfNode,
+    math::Vec3A,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use obvhs::{
+    aabb::Aabb,
+    cwbvh::{builder::build_cwbvh, CwBvh},
+    ray::{Ray, RayHit},
+    BvhBuildParams,
+};
+
+use thiserror::Error;
+
+use crate::{guns::weapon_def::TargetClass, util::all_children, GameLoading};
+
+pub struct HitVolumePlugin;
+impl Plugin for HitVolumePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HitVolumeBvh>()
+            .init_resource::<HitVolumeBones>()
+            .add_systems(
+                Update,
+                (attach_hit_volumes, build_hit_volume_bvh)
+                    .chain()
+                    .run_if(in_state(GameLoading::Loaded)),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HitZone {
+    Head,
+    Body,
+    Legs,
+}
+
+/// Capsule that follows a bone. `center` and `half_axis` are in the unit's root space at the
+/// rest pose, so the numbers can be read off the model without knowing the bone orientation. They're
+/// moved into the space of the bone with the rest pose from the glTF, see [`find_hit_volume_bones`].
+#[derive(Clone, Copy, Debug)]
+pub struct HitVolumeDef {
+    pub zone: HitZone,
+    /// glTF node the volume follows, it has to be there
+    pub bone: &'static str,
+    pub center: Vec3,
+    pub half_axis: Vec3,
+    pub radius: f32,
+}
+
+/// Add to a unit root, the volumes are attached once the scene has spawned
+#[derive(Component, Clone, Copy)]
+pub struct AddHitVolumes {
+    pub class: TargetClass,
+    /// Model the scene was spawned from, to look up the rest pose of the bones
+    pub gltf: AssetId<Gltf>,
+    pub volumes: &'static [HitVolumeDef],
+}
+
+/// Root space rest pose of the hit volume bones of each glTF, see [`find_hit_volume_bones`]
+#[derive(Resource, Default)]
+pub struct HitVolumeBones(HashMap<(AssetId<Gltf>, &'static str), Mat4>);
+
+#[derive(Debug, Error)]
+pub enum HitVolumeError {
+    #[error("{gltf} is missing hit volume bones {missing:?}, its nodes are {nodes:?}")]
+    MissingBones {
+        gltf: String,
+        missing: Vec<&'static str>,
+        nodes: Vec<String>,
+    },
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct HitVolume {
+    pub unit: Entity,
+    pub class: TargetClass,
+    pub zone: HitZone,
+    pub center: Vec3,
+    pub half_axis: Vec3,
+    pub radius: f32,
+}
+
+fn attach_hit_volumes(
+    mut commands: Commands,
+    units: Query<(Entity, &AddHitVolumes)>,
+    children_query: Query<&Children>,
+    names: Query<&Name>,
+    rest_poses: Res<HitVolumeBones>,
+) {
+    for (unit_entity, add) in &units {
+        let Ok(children) = children_query.get(unit_entity) else {
+            continue;
+        };
+        let mut found_bones = Vec::new();
+        all_children(children, &children_query, &mut |entity| {
+            if let Ok(name) = names.get(entity) {
+                found_bones.push((entity, name.as_str().to_string()));
+            }
+        });
+        if found_bones.is_empty() {
+            // Scene hasn't spawned yet
+            continue;
+        }
+        for def in add.volumes {
+            // Missing bones fail loading, see find_hit_volume_bones
+            let Some((bone_entity, _)) = found_bones.iter().find(|(_, name)| name == def.bone)
+            else {
+                continue;
+            };
+            let Some(rest) = rest_poses.0.get(&(add.gltf, def.bone)) else {
+                continue;
+            };
+            // Lines up with the root space at the rest pose, and follows the bone from there
+            let local = Transform::from_matrix(rest.inverse());
+            commands.entity(*bone_entity).with_children(|cmd| {
+                cmd.spawn((
+                    TransformBundle::from_transform(local),
+                    HitVolume {
+                        unit: unit_entity,
+                        class: add.class,
+                        zone: def.zone,
+                        center: def.center,
+                        half_axis: def.half_axis,
+                        radius: def.radius,
+                    },
+                ));
+            });
+        }
+        commands.entity(unit_entity).remove::<AddHitVolumes>();
+    }
+}
+
+/// Stores the rest pose of the bones of `volumes` in [`HitVolumeBones`]. Every bone has to be a node of the glTF.
+pub fn find_hit_volume_bones(
+    gltf_id: AssetId<Gltf>,
+    gltf_name: &str,
+    gltf: &Gltf,
+    node_assets: &Assets<GltfNode>,
+    volumes: &[HitVolumeDef],
+    rest_poses: &mut HitVolumeBones,
+) -> Result<(), HitVolumeError> {
+    let nodes: Vec<&GltfNode> = gltf
+        .nodes
+        .iter()
+        .filter_map(|node| node_assets.get(node))
+        .collect();
+    let child_indices: HashSet<usize> = nodes
+        .iter()
+        .flat_map(|node| node.children.iter().map(|child| child.index))
+        .collect();
+    let mut missing = Vec::new();
+    for def in volumes {
+        let rest = nodes
+            .iter()
+            .filter(|node| !child_indices.contains(&node.index))
+            .find_map(|root| rest_pose(root, Mat4::IDENTITY, def.bone));
+        match rest {
+            Some(rest) => {
+                rest_poses.0.insert((gltf_id, def.bone), rest);
+            }
+            None => missing.push(def.bone),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    missing.sort();
+    missing.dedup();
+    let mut nodes: Vec<String> = gltf
+        .named_nodes
+        .keys()
+        .map(|name| name.to_string())
+        .collect();
+    nodes.sort();
+    Err(HitVolumeError::MissingBones {
+        gltf: gltf_name.to_string(),
+        missing,
+        nodes,
+    })
+}
+
+/// Root space transform of the node named `name` under `node`
+fn rest_pose(node: &GltfNode, parent: Mat4, name: &str) -> Option<Mat4> {
+    let pose = parent * node.transform.compute_matrix();
+    if node.name == name {
+        return Some(pose);
+    }
+    node.children
+        .iter()
+        .find_map(|child| rest_pose(child, pose, name))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WorldCapsule {
+    pub volume: HitVolume,
+    pub a: Vec3A,
+    pub b: Vec3A,
+    pub radius: f32,
+}
+
+impl WorldCapsule {
+    pub fn aabb(&self) -> Aabb {
+        Aabb {
+            min: self.a.min(self.b) - self.radius,
+            max: self.a.max(self.b) + self.radius,
+        }
+    }
+
+    /// Distance along the ray to the capsule surface, f32::INFINITY if missed or outside the ray range.
+    /// Expects a normalized ray direction.
+    // https://iquilezles.org/articles/intersectors/
+    pub fn intersect(&self, ray: &Ray) -> f32 {
+        let ba = self.b - self.a;
+        let oa = ray.origin - self.a;
+        let baba = ba.dot(ba);
+        let bard = ba.dot(ray.direction);
+        let baoa = ba.dot(oa);
+        let rdoa = ray.direction.dot(oa);
+        let oaoa = oa.dot(oa);
+        let r2 = self.radius * self.radius;
+
+        let a = baba - bard * bard;
+        let mut b = baba * rdoa - baoa * bard;
+        let mut c = baba * oaoa - baoa * baoa - r2 * baba;
+        let mut h = b * b - a * c;
+        let mut t = f32::INFINITY;
+        if h >= 0.0 {
+            let body_t = (-b - h.sqrt()) / a;
+            let y = baoa + body_t * bard;
+            if y > 0.0 && y < baba {
+                t = body_t;
+            } else {
+                // Caps
+                let oc = if y <= 0.0 { oa } else { ray.origin - self.b };
+                b = ray.direction.dot(oc);
+                c = oc.dot(oc) - r2;
+                h = b * b - c;
+                if h > 0.0 {
+                    t = -b - h.sqrt();
+                }
+            }
+        }
+        if t.is_finite() && t >= ray.tmin && t <= ray.tmax {
+            t
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UnitHit {
+    pub unit: Entity,
+    pub class: TargetClass,
+    pub zone: HitZone,
+    pub t: f32,
+    pub point: Vec3,
+}
+
+/// Rebuilt every frame from all hit volumes
+#[derive(Resource, Default)]
+pub struct HitVolumeBvh {
+    pub bvh: Option<CwBvh>,
+    pub capsules: Vec<WorldCapsule>,
+}
+
+impl HitVolumeBvh {
+    /// Units along the ray, nearest first. Each unit is only reported once, for the first volume hit.
+    pub fn ray_hits(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_t: f32,
+        max_units: usize,
+    ) -> Vec<UnitHit> {
+        let mut hits: Vec<UnitHit> = Vec::new();
+        let Some(bvh) = &self.bvh else {
+            return hits;
+        };
+        let mut tmin = 0.0;
+        while hits.len() < max_units {
+            let ray = Ray::new(origin.into(), direction.normalize().into(), tmin, max_t);
+            let mut ray_hit = RayHit::none();
+            let found = bvh.ray_traverse(ray, &mut ray_hit, |ray, id| {
+                let capsule = &self.capsules[id];
+                if hits.iter().any(|hit| hit.unit == capsule.volume.unit) {
+                    return f32::INFINITY;
+                }
+                capsule.intersect(ray)
+            });
+            if !found {
+                break;
+            }
+            let capsule = &self.capsules[ray_hit.primitive_id as usize];
+            hits.push(UnitHit {
+                unit: capsule.volume.unit,
+                class: capsule.volume.class,
+                zone: capsule.volume.zone,
+                t: ray_hit.t,
+                point: origin + direction.normalize() * ray_hit.t,
+            });
+            tmin = ray_hit.t;
+        }
+        hits
+    }
+
+    /// Units with a volume within `radius` of `center`, nearest first. `t` is the distance to the volume surface.
+    pub fn units_in_radius(&self, center: Vec3, radius: f32) -> Vec<UnitHit> {
+        let center = Vec3A::from(center);
+        let mut hits: Vec<UnitHit> = Vec::new();
+        for capsule in &self.capsules {
+            let ba = capsule.b - capsule.a;
+            let h = ((center - capsule.a).dot(ba) / ba.length_squared().max(f32::EPSILON))
+                .clamp(0.0, 1.0);
+            let closest = capsule.a + ba * h;
+            let dist = (center.distance(closest) - capsule.radius).max(0.0);
+            if dist > radius {
+                continue;
+            }
+            let hit = UnitHit {
+                unit: capsule.volume.unit,
+                class: capsule.volume.class,
+                zone: capsule.volume.zone,
+                t: dist,
+                point: closest.into(),
+            };
+            match hits.iter_mut().find(|other| other.unit == hit.unit) {
+                Some(other) if other.t > dist => *other = hit,
+                Some(_) => (),
+                None => hits.push(hit),
+            }
+        }
+        hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+        hits
+    }
+}
+
+pub fn build_hit_volume_bvh(
+    mut hit_bvh: ResMut<HitVolumeBvh>,
+    volumes: Query<(&GlobalTransform, &HitVolume)>,
+) {
+    let hit_bvh = &mut *hit_bvh;
+    hit_bvh.capsules.clear();
+    for (global, volume) in &volumes {
+        let (scale, _, _) = global.to_scale_rotation_translation();
+        hit_bvh.capsules.push(WorldCapsule {
+            volume: *volume,
+            a: global
+                .transform_point(volume.center - volume.half_axis)
+                .into(),
+            b: global
+                .transform_point(volume.center + volume.half_axis)
+                .into(),
+            radius: volume.radius * scale.max_element(),
+        });
+    }
+    if hit_bvh.capsules.is_empty() {
+        hit_bvh.bvh = None;
+        return;
+    }
+    let aabbs = hit_bvh
+        .capsules
+        .iter()
+        .map(|capsule| capsule.aabb())
+        .collect::<Vec<_>>();
+    hit_bvh.bvh = Some(build_cwbvh(
+        &aabbs,
+        BvhBuildParams::fastest_build(),
+        &mut Duration::default(),
+    ));
+}
@@ -1,17 +1,82 @@
 use bevy::{prelude::*, render::view::NoFrustumCulling};
+use boss::{BossPlugin, BossUnitPlugin};
+use corpse::CorpsePlugin;
+use crowd::CrowdPlugin;
+use enemy_projectile::EnemyProjectilePlugin;
+use footsteps::FootstepPlugin;
+use hit_volumes::HitVolumePlugin;
+use perception::PerceptionPlugin;
 use plum::PlumUnitPlugin;
 use spider::SpiderUnitPlugin;
+use spitter::SpitterUnitPlugin;
 
-use crate::util::propagate_default;
+use crate::{
+    animation::{
+        AnimBlendPlugin, AnimLodPlugin, AnimNotifyPlugin, AnimStateMachinePlugin, RootMotionPlugin,
+    },
+    util::propagate_default,
+    GameLoading,
+};
 
+pub mod boss;
+pub mod corpse;
+pub mod crowd;
+pub mod enemy;
+pub mod enemy_projectile;
+pub mod footsteps;
 pub mod fox_unit;
+pub mod hit_volumes;
+pub mod perception;
 pub mod plum;
 pub mod spider;
+pub mod spitter;
 
 pub struct UnitsPlugin;
 impl Plugin for UnitsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((PlumUnitPlugin, SpiderUnitPlugin))
-            .add_systems(Update, propagate_default::<NoFrustumCulling, Handle<Mesh>>);
+        app.add_plugins((
+            PlumUnitPlugin::default(),
+            SpiderUnitPlugin::default(),
+            SpitterUnitPlugin::default(),
+            BossUnitPlugin::default(),
+            HitVolumePlugin,
+            CrowdPlugin,
+            PerceptionPlugin,
+            EnemyProjectilePlugin,
+            BossPlugin,
+            CorpsePlugin,
+            RootMotionPlugin,
+            AnimNotifyPlugin,
+            AnimStateMachinePlugin,
+            AnimBlendPlugin,
+            AnimLodPlugin,
+        ))
+        .add_plugins(FootstepPlugin)
+        .add_systems(Update, propagate_default::<NoFrustumCulling, Handle<Mesh>>)
+        .add_systems(
+            Update,
+            update_explosion.run_if(in_state(GameLoading::Loaded)),
+        );
+    }
+}
+
+#[derive(Component)]
+pub struct Explosion(pub f32);
+
+fn update_explosion(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Transform, &mut Explosion)>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut trans, mut exp) in &mut query {
+        trans.translation.y += dt * 10.0;
+        trans.scale += dt * Vec3::ONE * 30.0;
+
+        if exp.0 > 1.0 {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        exp.0 += dt;
     }
 }
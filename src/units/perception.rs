@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{character_controller::Player, damage::DamageEvent, physics::LevelCollider};
+
+use super::enemy::{EnemyArchetype, EnemyUnit};
+
+/// Seconds between line of sight checks, staggered between units
+const SIGHT_CHECK_INTERVAL: f32 = 0.2;
+/// Units closer than this to the spot they are investigating have arrived and start searching around
+pub const ARRIVE_DIST: f32 = 4.0;
+
+pub struct PerceptionPlugin;
+impl Plugin for PerceptionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GunfireEvent>();
+    }
+}
+
+/// Sent by `fire_gun` for each shot, units that hear it come to investigate
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GunfireEvent {
+    pub origin: Vec3,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PerceptionDef {
+    /// Max distance the player can be seen from
+    pub sight_dist: f32,
+    /// Cosine of half the vision cone angle. Units that are chasing see all around them.
+    pub fov_cos: f32,
+    /// Player is noticed within this distance no matter where the unit is looking
+    pub sense_dist: f32,
+    /// Gunfire within this distance is heard
+    pub hearing_radius: f32,
+    /// Seconds a unit looks around the last known position before going idle
+    pub search_time: f32,
+    /// Height of the eyes above the unit origin, before SCALE
+    pub eye_height: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AggroState {
+    /// Stands still until it sees or hears the player
+    #[default]
+    Idle,
+    /// Heard gunfire or got hurt, goes to check it out
+    Alert,
+    /// Lost sight of the player, goes to where it was last seen
+    Search,
+    /// Sees the player
+    Chase,
+}
+
+/// What a unit knows about the player
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Perception {
+    pub state: AggroState,
+    /// Where the player was last seen, or where the noise came from
+    pub last_known: Option<Vec3>,
+    /// When the unit arrived at `last_known`
+    searching_since: Option<f32>,
+    next_sight_check: f32,
+}
+
+impl Perception {
+    /// Sends the unit to investigate `position`, unless it's already chasing
+    pub fn alert(&mut self, position: Vec3) {
+        if self.state == AggroState::Chase {
+            return;
+        }
+        self.state = AggroState::Alert;
+        self.last_known = Some(position);
+        self.searching_since = None;
+    }
+
+    fn go_idle(&mut self) {
+        self.state = AggroState::Idle;
+        self.last_known = None;
+        self.searching_since = None;
+    }
+}
+
+pub fn update_perception<T: EnemyArchetype>(
+    mut units: Query<(Entity, &Transform, &mut Perception), With<EnemyUnit<T>>>,
+    player: Query<(Entity, &Transform, &Player), With<Camera3d>>,
+    mut gunfire_events: EventReader<GunfireEvent>,
+    mut damage_events: EventReader<DamageEvent>,
+    level_filter: Query<(), With<LevelCollider>>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
+) {
+    let Ok((player_entity, player_trans, player_stats)) = player.get_single() else {
+        return;
+    };
+    let def = T::PERCEPTION;
+    let t = time.elapsed_seconds();
+    let player_pos = player_trans.translation;
+
+    if player_stats.health < 0.0 {
+        for (_, _, mut perception) in &mut units {
+            perception.go_idle();
+        }
+        gunfire_events.clear();
+        damage_events.clear();
+        return;
+    }
+
+    for event in gunfire_events.read() {
+        for (_, trans, mut perception) in &mut units {
+            if trans.translation.distance(event.origin) < def.hearing_radius {
+                perception.alert(event.origin);
+            }
+        }
+    }
+    // Getting shot gives away where the player is
+    for event in damage_events.read() {
+        if event.source != Some(player_entity) {
+            continue;
+        }
+        if let Ok((_, _, mut perception)) = units.get_mut(event.target) {
+            perception.alert(player_pos);
+        }
+    }
+
+    let predicate = |entity| level_filter.contains(entity);
+    let filter = QueryFilter::default()
+        .exclude_sensors()
+        .predicate(&predicate);
+
+    for (entity, trans, mut perception) in &mut units {
+        if t > perception.next_sight_check {
+            // Stagger the checks so they don't all land on the same frame
+            let jitter = (entity.index() % 16) as f32 / 16.0;
+            perception.next_sight_check = t + SIGHT_CHECK_INTERVAL * (1.0 + jitter * 0.5);
+
+            let eye = trans.translation + Vec3::Y * def.eye_height * T::SCALE;
+            let to_player = player_pos - eye;
+            let dist = to_player.length();
+            let dir = to_player / dist.max(0.001);
+            let in_view = dist < def.sense_dist
+                || (dist < def.sight_dist
+                    && (perception.state == AggroState::Chase
+                        || dir.dot(*trans.forward()) > def.fov_cos));
+            let sees_player = in_view
+                && rapier_context
+                    .cast_ray(eye, dir, dist, true, filter)
+                    .is_none();
+
+            if sees_player {
+                perception.state = AggroState::Chase;
+                perception.last_known = Some(player_pos);
+                perception.searching_since = None;
+            } else if perception.state == AggroState::Chase {
+                perception.state = AggroState::Search;
+                perception.searching_since = None;
+            }
+        } else if perception.state == AggroState::Chase {
+            perception.last_known = Some(player_pos);
+        }
+
+        if matches!(perception.state, AggroState::Alert | AggroState::Search) {
+            let Some(last_known) = perception.last_known else {
+                perception.go_idle();
+                continue;
+            };
+            if trans.translation.xz().distance(last_known.xz()) < ARRIVE_DIST {
+                let since = *perception.searching_since.get_or_insert(t);
+                if t > since + def.search_time {
+                    perception.go_idle();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{
+    animation::{AnimNotifies, AnimStateMachine},
+    damage::{DamageKind, Damageable},
+    guns::weapon_def::TargetClass,
+    mesh_assets::{MeshAssets, StingerClip},
+};
+
+use bevy::{animation::ActiveAnimation, prelude::*};
+
+use super::{
+    corpse::CorpseDef,
+    enemy::{
+        face_dest, AttackContext, AttackStyle, EnemyArchetype, EnemyBehaviour, EnemyUnit,
+        EnemyUnitPlugin,
+    },
+    enemy_projectile::{lob_velocity, spawn_enemy_projectile, EnemyProjectile},
+    hit_volumes::{HitVolumeDef, HitZone},
+    perception::PerceptionDef,
+    spider::{spider_walk_step, Spider},
+    Explosion,
+};
+
+pub type SpitterUnitPlugin = EnemyUnitPlugin<Spitter>;
+pub type SpitterUnit = EnemyUnit<Spitter>;
+
+/// Distance from the player the spitter tries to keep
+const SPITTER_RANGE: f32 = 30.0;
+/// How far around the player the spitter circles, in radians
+const SPITTER_STRAFE_ANGLE: f32 = 0.6;
+/// Seconds before the spitter switches which way it circles
+const SPITTER_STRAFE_TIME: f32 = 4.0;
+const SPIT_SPEED: f32 = 25.0;
+const SPIT_GRAVITY: f32 = 6.0;
+const SPIT_RADIUS: f32 = 0.5;
+const SPIT_DAMAGE: f32 = 12.0;
+const SPIT_LIFETIME: f32 = 6.0;
+/// Where the spit leaves the model, before SCALE
+const SPIT_ORIGIN: Vec3 = Vec3::new(0.0, 2.0, -2.6);
+
+#[derive(Clone, Copy, Default)]
+pub struct Spitter;
+
+/// Uses the spider model, with its own behaviour
+impl EnemyArchetype for Spitter {
+    type Clip = StingerClip;
+    const NAME: &'static str = "Spitter";
+    const CLASS: TargetClass = TargetClass::Spitter;
+    const SCALE: f32 = 0.7;
+    const HIT_VOLUMES: &'static [HitVolumeDef] = Spider::HIT_VOLUMES;
+    const DAMAGEABLE: Damageable = Damageable {
+        health: 80.0,
+        resistances: &[(DamageKind::Explosion, 0.75)],
+        zone_multipliers: &[(HitZone::Head, 2.0), (HitZone::Legs, 0.75)],
+    };
+    const BEHAVIOUR: EnemyBehaviour = EnemyBehaviour {
+        attack_style: AttackStyle::OneShot,
+        attack_dist: SPITTER_RANGE * 1.5,
+        attack_cooldown: 3.0,
+        stop_dist: 3.0,
+    };
+    const CROWD_RADIUS: f32 = 1.5;
+    const PERCEPTION: PerceptionDef = PerceptionDef {
+        sight_dist: 100.0,
+        fov_cos: 0.6,
+        sense_dist: 8.0,
+        hearing_radius: 150.0,
+        search_time: 10.0,
+        eye_height: 1.5,
+    };
+    const CORPSE: Option<CorpseDef> = Some(CorpseDef {
+        center: Vec3::new(0.0, 1.6, 0.0),
+        half_extents: Vec3::new(2.0, 0.8, 2.4),
+        impulse_per_damage: 0.3,
+        lifetime: 10.0,
+    });
+
+    fn scene(mesh_assets: &MeshAssets) -> Handle<Scene> {
+        mesh_assets.spider.clone()
+    }
+
+    fn gltf(mesh_assets: &MeshAssets) -> Handle<Gltf> {
+        mesh_assets.spider_gltf.clone_weak()
+    }
+
+    fn anim_state_machine(mesh_assets: &MeshAssets) -> Handle<AnimStateMachine> {
+        mesh_assets.spitter_anim.clone()
+    }
+
+    fn notifies(mesh_assets: &MeshAssets) -> Option<Handle<AnimNotifies>> {
+        Some(mesh_assets.spider_notifies.clone())
+    }
+
+    /// Circles the player at range, each unit switching direction on its own schedule
+    fn chase_dest(unit_entity: Entity, unit_pos: Vec3, player_pos: Vec3, t: f32) -> Vec3 {
+        let mut away = unit_pos - player_pos;
+        away.y = 0.0;
+        let away = away.normalize_or(Vec3::Z);
+        let phase = (t / SPITTER_STRAFE_TIME) as u32 + unit_entity.index();
+        let side = if phase % 2 == 0 { 1.0 } else { -1.0 };
+        player_pos + Quat::from_rotation_y(side * SPITTER_STRAFE_ANGLE) * away * SPITTER_RANGE
+    }
+
+    fn walk_step(anim: &ActiveAnimation, dt: f32) -> Option<(f32, f32)> {
+        spider_walk_step(Self::SCALE, anim, dt)
+    }
+
+    fn turn_step(anim: &ActiveAnimation, dt: f32) -> f32 {
+        Spider::turn_step(anim, dt)
+    }
+
+    /// Winds up facing the player until the "spit" notify
+    fn attack(ctx: &mut AttackContext) {
+        if !ctx.notified("spit") {
+            face_dest(ctx.unit_trans, ctx.dest, 0.2 * ctx.anim.speed());
+            return;
+        }
+        let origin = ctx.unit_trans.transform_point(SPIT_ORIGIN);
+        spawn_enemy_projectile(
+            ctx.commands,
+            ctx.mesh_assets,
+            origin,
+            EnemyProjectile {
+                velocity: lob_velocity(origin, ctx.dest, SPIT_SPEED, SPIT_GRAVITY),
+                gravity: SPIT_GRAVITY,
+                radius: SPIT_RADIUS,
+                damage: SPIT_DAMAGE,
+                spawned: ctx.t,
+                lifetime: SPIT_LIFETIME,
+                source: ctx.unit_entity,
+            },
+        );
+    }
+
+    fn on_death(commands: &mut Commands, mesh_assets: &MeshAssets, trans: &Transform) {
+        commands.spawn((
+            SceneBundle {
+                scene: mesh_assets.exp.clone(),
+                transform: Transform::from_translation(trans.translation),
+                ..default()
+            },
+            Explosion(0.0),
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 
-use bevy::prelude::*;
+use bevy::{ecs::system::EntityCommands, prelude::*};
 pub const FRAC_1_TAU: f32 = 0.15915494309;
 
 pub fn all_children<F: FnMut(Entity)>(
@@ -109,3 +109,94 @@ pub fn pfract(x: f32) -> f32 {
         y
     }
 }
+
+/// Fixed size pool of entities. Once the cap is reached the oldest entity is reused for the next one. `T` is kept
+/// with each entity, e.g. the scene it was spawned with.
+#[derive(Clone, Debug)]
+pub struct EntityRing<T = ()> {
+    slots: Vec<(Entity, T)>,
+    /// Oldest entity, the next one to be reused
+    next: usize,
+}
+
+impl<T> Default for EntityRing<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            next: 0,
+        }
+    }
+}
+
+/// Where the next entity of an [`EntityRing`] goes
+pub enum RingSlot<'a, T> {
+    /// The oldest entity, it's the newest from now on
+    Reuse(EntityCommands<'a>, &'a mut T),
+    /// Spawn a new entity and [`EntityRing::push`] it
+    Spawn,
+}
+
+impl<T> EntityRing<T> {
+    /// Despawns the oldest entities over `cap`, for when it was lowered
+    pub fn trim(&mut self, commands: &mut Commands, cap: usize) {
+        if self.slots.len() > cap {
+            self.oldest_first();
+            let excess = self.slots.len() - cap;
+            for (entity, _) in self.slots.drain(..excess) {
+                if let Some(ecmds) = commands.get_entity(entity) {
+                    ecmds.despawn_recursive();
+                }
+            }
+        }
+    }
+
+    /// Trims the pool down to `cap`, then picks the slot for the next entity. None if `cap` is 0.
+    pub fn next<'a>(
+        &'a mut self,
+        commands: &'a mut Commands,
+        cap: usize,
+    ) -> Option<RingSlot<'a, T>> {
+        self.trim(commands, cap);
+        if cap == 0 {
+            return None;
+        }
+        if self.slots.len() < cap {
+            return Some(RingSlot::Spawn);
+        }
+        let index = self.next % self.slots.len();
+        if commands.get_entity(self.slots[index].0).is_none() {
+            // Despawned by something else, a new one takes its place
+            self.slots.remove(index);
+            self.next = index;
+            return Some(RingSlot::Spawn);
+        }
+        self.next = index + 1;
+        let (entity, data) = &mut self.slots[index];
+        Some(RingSlot::Reuse(commands.entity(*entity), data))
+    }
+
+    /// Adds a new entity after the newest one
+    pub fn push(&mut self, entity: Entity, data: T) {
+        self.oldest_first();
+        self.slots.push((entity, data));
+    }
+
+    /// Despawns every entity, e.g. for restarting the level
+    pub fn despawn_all(&mut self, commands: &mut Commands) {
+        for (entity, _) in self.slots.drain(..) {
+            if let Some(ecmds) = commands.get_entity(entity) {
+                ecmds.despawn_recursive();
+            }
+        }
+        self.next = 0;
+    }
+
+    /// Rotates the ring so the oldest entity is first
+    fn oldest_first(&mut self) {
+        if !self.slots.is_empty() {
+            let next = self.next % self.slots.len();
+            self.slots.rotate_left(next);
+        }
+        self.next = 0;
+    }
+}
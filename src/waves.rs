@@ -0,0 +1,347 @@
+use anyhow::Result;
+use bevy::asset::io::Reader;
+use bevy::asset::{Asset, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::math::vec3;
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy_asset_loader::asset_collection::AssetCollection;
+use bevy_egui::{egui, EguiContexts};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    character_controller::Player,
+    difficulty::{Difficulty, DifficultyDef},
+    guns::weapon_def::TargetClass,
+    hash_noise,
+    menu::menu_ui,
+    units::boss::BossFight,
+    GameLoading, LEVEL_MAIN_FLOOR,
+};
+
+/// Spawn points closer than this to the player are only used if there are no others
+const MIN_SPAWN_DISTANCE: f32 = 40.0;
+/// Random offset in each horizontal axis so units from the same spawn point don't stack up
+const SPAWN_JITTER: f32 = 3.0;
+/// Used when the level has no SPAWN_POINT nodes
+const FALLBACK_SPAWN_CENTER: Vec3 = vec3(0.0, LEVEL_MAIN_FLOOR, -800.0);
+const FALLBACK_SPAWN_EXTENTS: Vec2 = Vec2::new(500.0, 250.0);
+/// Seconds the "WAVE N" banner stays on screen
+const BANNER_TIME: f32 = 3.0;
+
+pub struct WavePlugin;
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<WaveScript>()
+            .init_asset_loader::<WaveScriptLoader>()
+            .init_resource::<WaveDirector>()
+            .add_event::<SpawnEnemyEvent>()
+            .add_systems(
+                Update,
+                (direct_waves, wave_banner)
+                    .chain()
+                    .run_if(in_state(GameLoading::Loaded))
+                    .before(menu_ui),
+            );
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct WaveAssets {
+    #[asset(path = "waves/arena.waves.ron")]
+    pub script: Handle<WaveScript>,
+}
+
+/// One kind of unit a wave can spend its budget on
+#[derive(Clone, Debug, Deserialize)]
+pub struct WaveUnitDef {
+    pub class: TargetClass,
+    /// Budget spent per unit
+    pub cost: u32,
+    /// Relative chance of being picked among the units the remaining budget can afford
+    pub weight: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WaveDef {
+    /// Units are spawned until this is spent
+    pub budget: u32,
+    pub composition: Vec<WaveUnitDef>,
+    /// Seconds between spawns
+    pub spawn_interval: f32,
+    /// Spawning pauses while this many wave units are alive
+    pub max_alive: usize,
+    /// Seconds between this wave being cleared and the next one starting
+    pub rest_time: f32,
+}
+
+/// Describes the waves of a level. Loaded from `.waves.ron` files.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct WaveScript {
+    /// Seconds between the player reaching the arena and the first wave
+    pub start_delay: f32,
+    pub waves: Vec<WaveDef>,
+    /// Once the script runs out the last wave repeats, its budget multiplied by this for each extra wave
+    pub endless_budget_scale: f32,
+    pub difficulty: DifficultyDef,
+}
+
+impl WaveScript {
+    /// Wave number starts at 1
+    pub fn wave(&self, number: u32) -> Option<(&WaveDef, u32)> {
+        let last = self.waves.len().checked_sub(1)?;
+        let index = (number.saturating_sub(1) as usize).min(last);
+        let def = &self.waves[index];
+        let extra = number.saturating_sub(self.waves.len() as u32);
+        let budget = def.budget as f32 * self.endless_budget_scale.powi(extra as i32);
+        Some((def, budget.round() as u32))
+    }
+}
+
+/// Marks where waves can spawn units. Added to level glTF nodes with SPAWN_POINT in their name.
+#[derive(Component, Clone)]
+pub struct SpawnPoint;
+
+/// Read by the spawner of the archetype with a matching [`TargetClass`]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SpawnEnemyEvent {
+    pub class: TargetClass,
+    pub position: Vec3,
+}
+
+/// Unit spawned by the [`WaveDirector`], a wave is cleared once none are left
+#[derive(Component, Clone, Copy)]
+pub struct WaveUnit;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WaveState {
+    /// Player hasn't reached the arena yet
+    #[default]
+    Waiting,
+    /// Spawning the wave or waiting for the player to clear it
+    Active,
+    /// Between waves, until the given elapsed time
+    Resting { until: f32 },
+}
+
+/// Spawns enemies following the [`WaveScript`]. Reset when the player restarts.
+#[derive(Resource, Default)]
+pub struct WaveDirector {
+    pub state: WaveState,
+    /// Current or last wave, starts at 1. 0 before the first wave.
+    pub wave: u32,
+    /// Budget the current wave can still spend on units
+    pub budget_left: u32,
+    wave_started: f32,
+    last_spawn: f32,
+    spawn_count: u32,
+}
+
+impl WaveDirector {
+    fn start_wave(&mut self, script: &WaveScript, t: f32) {
+        self.wave += 1;
+        self.budget_left = script.wave(self.wave).map_or(0, |(_, budget)| budget);
+        self.state = WaveState::Active;
+        self.wave_started = t;
+        self.last_spawn = f32::MIN;
+        info!("Wave {} started, budget {}", self.wave, self.budget_left);
+    }
+
+    /// Weighted random pick among the units the remaining budget can afford
+    fn pick_unit<'a>(&self, def: &'a WaveDef) -> Option<&'a WaveUnitDef> {
+        let affordable = || {
+            def.composition
+                .iter()
+                .filter(|unit| unit.cost <= self.budget_left && unit.weight > 0.0)
+        };
+        let total: f32 = affordable().map(|unit| unit.weight).sum();
+        let mut pick = hash_noise(self.spawn_count, self.wave, 3) * total;
+        let mut last = None;
+        for unit in affordable() {
+            if pick < unit.weight {
+                return Some(unit);
+            }
+            pick -= unit.weight;
+            last = Some(unit);
+        }
+        last
+    }
+
+    fn spawn_position(&self, player_pos: Vec3, spawn_points: &[Vec3]) -> Vec3 {
+        let n = self.spawn_count;
+        let rng = |axis| hash_noise(n, self.wave, axis) * 2.0 - 1.0;
+        if spawn_points.is_empty() {
+            return FALLBACK_SPAWN_CENTER
+                + vec3(
+                    rng(0) * FALLBACK_SPAWN_EXTENTS.x,
+                    0.0,
+                    rng(1) * FALLBACK_SPAWN_EXTENTS.y,
+                );
+        }
+        let far: Vec<Vec3> = spawn_points
+            .iter()
+            .copied()
+            .filter(|p| p.distance(player_pos) > MIN_SPAWN_DISTANCE)
+            .collect();
+        let candidates = if far.is_empty() { spawn_points } else { &far };
+        let index = (hash_noise(n, self.wave, 2) * candidates.len() as f32) as usize;
+        candidates[index.min(candidates.len() - 1)]
+            + vec3(rng(0) * SPAWN_JITTER, 0.0, rng(1) * SPAWN_JITTER)
+    }
+}
+
+fn direct_waves(
+    mut director: ResMut<WaveDirector>,
+    mut spawn_events: EventWriter<SpawnEnemyEvent>,
+    player: Query<(&Transform, &Player), With<Camera3d>>,
+    spawn_points: Query<&GlobalTransform, With<SpawnPoint>>,
+    wave_units: Query<(), With<WaveUnit>>,
+    wave_assets: Res<WaveAssets>,
+    scripts: Res<Assets<WaveScript>>,
+    (difficulty, boss_fight): (Res<Difficulty>, Res<BossFight>),
+    time: Res<Time>,
+) {
+    let Ok((player_trans, player_stats)) = player.get_single() else {
+        return;
+    };
+    let Some(script) = scripts.get(&wave_assets.script) else {
+        return;
+    };
+    let t = time.elapsed_seconds();
+
+    // Player stats are reset on restart
+    if player_stats.activity_start_time.is_none() {
+        if director.state != WaveState::Waiting {
+            *director = WaveDirector::default();
+        }
+        return;
+    }
+    // Waves hold off while the player is locked in with the boss
+    if player_stats.health < 0.0 || boss_fight.active() {
+        return;
+    }
+
+    match director.state {
+        WaveState::Waiting => {
+            director.state = WaveState::Resting {
+                until: t + script.start_delay,
+            };
+        }
+        WaveState::Resting { until } => {
+            if t > until {
+                director.start_wave(script, t);
+            }
+        }
+        WaveState::Active => {
+            let Some((def, _)) = script.wave(director.wave) else {
+                return;
+            };
+            let alive = wave_units.iter().len();
+            if director.budget_left == 0 {
+                if alive == 0 {
+                    info!("Wave {} cleared", director.wave);
+                    director.state = WaveState::Resting {
+                        until: t + def.rest_time,
+                    };
+                }
+                return;
+            }
+            let spawn_interval = def.spawn_interval / difficulty.spawn_rate;
+            if alive >= def.max_alive || t < director.last_spawn + spawn_interval {
+                return;
+            }
+            let Some(unit) = director.pick_unit(def) else {
+                // Nothing left is affordable
+                director.budget_left = 0;
+                return;
+            };
+            let spawn_points: Vec<Vec3> = spawn_points.iter().map(|p| p.translation()).collect();
+            spawn_events.send(SpawnEnemyEvent {
+                class: unit.class,
+                position: director.spawn_position(player_trans.translation, &spawn_points),
+            });
+            director.budget_left -= unit.cost;
+            director.last_spawn = t;
+            director.spawn_count += 1;
+        }
+    }
+}
+
+fn wave_banner(mut contexts: EguiContexts, director: Res<WaveDirector>, time: Res<Time>) {
+    let t = time.elapsed_seconds();
+    let (text, alpha) = match director.state {
+        WaveState::Waiting => return,
+        WaveState::Active => {
+            let age = t - director.wave_started;
+            if age > BANNER_TIME {
+                return;
+            }
+            // Fade out over the last second
+            let alpha = (BANNER_TIME - age).clamp(0.0, 1.0);
+            (format!("WAVE {}", director.wave), alpha)
+        }
+        WaveState::Resting { until } => {
+            let text = if director.wave == 0 {
+                format!("FIRST WAVE IN {:.0}", (until - t).ceil())
+            } else {
+                format!(
+                    "WAVE {} CLEARED\nNEXT WAVE IN {:.0}",
+                    director.wave,
+                    (until - t).ceil()
+                )
+            };
+            (text, 1.0)
+        }
+    };
+
+    let ctx = contexts.ctx_mut();
+    let size = ctx.available_rect();
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    painter.text(
+        egui::Pos2::new(size.width() * 0.5, size.height() * 0.2),
+        egui::Align2::CENTER_CENTER,
+        text,
+        egui::FontId {
+            size: 40.0,
+            family: egui::FontFamily::Monospace,
+        },
+        egui::Color32::from_rgba_unmultiplied(255, 255, 255, (alpha * 160.0) as u8),
+    );
+}
+
+/// Possible errors that can be produced by [`WaveScriptLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum WaveScriptLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON Error](ron::error::SpannedError)
+    #[error("Could not parse RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+/// Asset loader for `.waves.ron` files.
+#[derive(Default)]
+pub struct WaveScriptLoader;
+
+impl AssetLoader for WaveScriptLoader {
+    type Asset = WaveScript;
+    type Settings = ();
+    type Error = WaveScriptLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes::<WaveScript>(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["waves.ron"]
+    }
+}